@@ -1,16 +1,20 @@
 #![windows_subsystem = "windows"]
 
 use brengin::{
-    assets::{self, Assets, Handle},
-    camera::{PerspectiveCamera, WindowCamera},
-    glam::{Quat, Vec2, Vec3},
+    assets,
+    camera::{
+        controllers::{CameraController, CameraControllerPlugin},
+        PerspectiveCamera, WindowCamera,
+    },
+    glam::{Vec2, Vec3},
     prelude::*,
     renderer::{
         camera_bundle,
         sprite_renderer::{self, SpriteSheet},
     },
     transform::{self, transform_bundle, Transform},
-    App, DefaultPlugins, DeltaTime, Plugin, Stage,
+    winit::keyboard::KeyCode,
+    App, DefaultPlugins, DeltaTime, GameLoopControl, KeyBoardInputs, Plugin, Stage,
 };
 use tracing::info;
 
@@ -67,9 +71,8 @@ fn update_boids(
 
 fn update_transform(mut q: Query<(&mut Transform, &Velocity, &Pos)>) {
     q.par_for_each_mut(|(tr, Velocity(vel), p)| {
-        let angle = -vel.x.atan2(vel.y);
-        tr.rot = Quat::from_rotation_z(angle);
-        tr.pos = p.extend(0.0);
+        tr.set_angle(-vel.x.atan2(vel.y));
+        tr.set_xy(p.0);
     });
 }
 
@@ -89,6 +92,7 @@ fn setup_boids(mut cmd: Commands, mut assets: ResMut<assets::Assets<SpriteSheet>
     //camera
     cmd.spawn()
         .insert(WindowCamera)
+        .insert(CameraController::pan_2d())
         .insert_bundle(camera_bundle(PerspectiveCamera {
             eye: Vec3::new(0.0, 0.0, 100.0),
             target: Vec3::ZERO,
@@ -100,12 +104,11 @@ fn setup_boids(mut cmd: Commands, mut assets: ResMut<assets::Assets<SpriteSheet>
         }))
         .insert_bundle(transform_bundle(transform::Transform::default()));
 
-    let boid = load_sprite_sheet(
-        include_bytes!("assets/boid.png"),
-        Vec2::splat(32.0),
-        1,
-        &mut assets,
-    );
+    // the boids are drawn much smaller than their texture when zoomed out
+    let boid = SpriteSheet::decode(include_bytes!("assets/boid.png"), &Default::default())
+        .expect("Failed to load spritesheet")
+        .with_mips(true);
+    let boid = assets.insert(boid);
 
     info!(N, "Spawning boids");
     for _ in 0..N {
@@ -130,16 +133,16 @@ fn setup_boids(mut cmd: Commands, mut assets: ResMut<assets::Assets<SpriteSheet>
     }
 }
 
-fn load_sprite_sheet(
-    bytes: &[u8],
-    box_size: Vec2,
-    num_cols: u32,
-    assets: &mut Assets<SpriteSheet>,
-) -> Handle<SpriteSheet> {
-    let image = image::load_from_memory(bytes).expect("Failed to load spritesheet");
-    let sprite_sheet = SpriteSheet::from_image(Vec2::ZERO, box_size, num_cols, image);
-
-    assets.insert(sprite_sheet)
+/// Space: pause/resume the simulation
+/// N: step a single tick while paused
+fn debug_controls(keys: Res<KeyBoardInputs>, mut control: ResMut<GameLoopControl>) {
+    if keys.just_pressed.contains(&KeyCode::Space) {
+        control.toggle_pause();
+        info!(paused = control.paused, "Toggled pause");
+    }
+    if control.paused && keys.just_pressed.contains(&KeyCode::KeyN) {
+        control.step();
+    }
 }
 
 struct GamePlugin;
@@ -150,9 +153,12 @@ impl Plugin for GamePlugin {
             s.add_system(update_boids)
                 .add_system(update_boids_vel.after(update_boids))
                 .add_system(update_boids_pos.after(update_boids))
-                .add_system(update_transform.after(update_boids));
+                .add_system(update_transform.after(update_boids))
+                .add_system(debug_controls);
         });
+        app.add_paused_system(debug_controls);
 
+        app.add_plugin(CameraControllerPlugin);
         app.add_startup_system(setup_boids);
         app.insert_resource(BoidConfig {
             radius: 30.0,
@@ -1,6 +1,8 @@
 use brengin::camera::{camera_bundle, PerspectiveCamera, WindowCamera};
 use brengin::prelude::*;
-use brengin::renderer::{GraphicsState, RenderCommand, RenderCommandPlugin};
+use brengin::renderer::{
+    CustomRenderer, CustomRendererPlugin, GraphicsState, RenderCommandInput, RenderPass,
+};
 use brengin::{App, DefaultPlugins, Plugin};
 use glam::Vec3;
 use wgpu::include_wgsl;
@@ -23,12 +25,21 @@ fn setup(mut cmd: Commands) {
         .insert_bundle(transform_bundle(Transform::default()));
 }
 
+/// Draws a fullscreen mandelbrot set behind the UI.
+///
+/// The pipeline lives in the render world, [CustomRendererPlugin] creates it once the GPU is
+/// available, extracts the game data it needs every frame, and draws it in `PASS`.
 struct MandelbrotPipeline {
     render_pipeline: wgpu::RenderPipeline,
+    /// Render-side copy of the game's delta time
+    dt: std::time::Duration,
+    extracted: LastExtracted,
 }
 
-impl MandelbrotPipeline {
-    fn new(renderer: &GraphicsState) -> Self {
+impl CustomRenderer for MandelbrotPipeline {
+    const PASS: RenderPass = RenderPass::Ui;
+
+    fn setup(renderer: &GraphicsState) -> Self {
         let render_pipeline_layout =
             renderer
                 .device()
@@ -86,42 +97,50 @@ impl MandelbrotPipeline {
                     cache: None,
                 });
 
-        Self { render_pipeline }
+        Self {
+            render_pipeline,
+            dt: Default::default(),
+            extracted: Default::default(),
+        }
     }
-}
 
-impl<'a> RenderCommand<'a> for MandelbrotPipeline {
-    type Parameters = Res<'a, MandelbrotPipeline>;
-    fn render<'r>(
-        brengin::renderer::RenderCommandInput {
-            render_pass,
-            ..
-        }: &'r mut brengin::renderer::RenderCommandInput<'a>,
+    fn extract(&mut self, game_world: &World, tick: ExtractionTick) {
+        if let Some(dt) = game_world.get_resource::<DeltaTime>() {
+            self.dt = dt.0;
+            self.extracted.mark(tick);
+        }
+    }
 
-        pipeline: &'r Self::Parameters,
+    fn render(
+        &self,
+        RenderCommandInput {
+            render_pass, stats, ..
+        }: &mut RenderCommandInput,
     ) {
-        render_pass.set_pipeline(&pipeline.render_pipeline);
+        render_pass.set_pipeline(&self.render_pipeline);
         render_pass.draw(0..3, 0..1);
+        stats.pipeline_switches += 1;
+        stats.draw(1);
+    }
+}
+
+fn report_stale_frame_time(pipeline: Res<MandelbrotPipeline>, tick: Res<ExtractionTick>) {
+    if pipeline.extracted.is_stale(*tick) {
+        tracing::debug!(dt = ?pipeline.dt, "Rendering with a stale frame time");
     }
 }
 
 impl Plugin for GamePlugin {
     fn build(self, app: &mut brengin::App) {
         app.add_startup_system(setup);
-        app.add_plugin(RenderCommandPlugin::<MandelbrotPipeline>::new(
-            brengin::renderer::RenderPass::Ui,
-        ));
-        if let Some(ref mut app) = app.render_app {
-            app.add_startup_system(setup_pipeline);
-        }
+        app.add_plugin(CustomRendererPlugin::<MandelbrotPipeline>::default());
+        app.render_app_mut()
+            .with_stage(brengin::Stage::Update, |s| {
+                s.add_system(report_stale_frame_time);
+            });
     }
 }
 
-fn setup_pipeline(mut cmd: Commands, graphics_state: Res<GraphicsState>) {
-    let pl = MandelbrotPipeline::new(&graphics_state);
-    cmd.insert_resource(pl);
-}
-
 async fn game() {
     let mut app = App::default();
     app.add_plugin(DefaultPlugins);
@@ -0,0 +1,80 @@
+//! Diffuses heat over a large grid, using the JobPool to update rows in parallel
+use std::time::Duration;
+
+use brengin::prelude::*;
+use brengin::{App, DefaultPlugins, Plugin};
+use tracing::info;
+
+const WIDTH: usize = 1024;
+const HEIGHT: usize = 1024;
+
+struct Grid {
+    current: Vec<f32>,
+    next: Vec<f32>,
+}
+
+struct LogTimer(Timer);
+
+fn diffuse_system(mut grid: ResMut<Grid>, pool: Res<JobPool>) {
+    let Grid { current, next } = &mut *grid;
+    let current = &*current;
+    pool.scope(|s| {
+        for (y, row) in next.chunks_mut(WIDTH).enumerate() {
+            s.spawn(move |_s| {
+                for (x, out) in row.iter_mut().enumerate() {
+                    let get = |x: usize, y: usize| current[y * WIDTH + x];
+                    let mut sum = get(x, y);
+                    let mut n = 1.0;
+                    for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                        let nx = x as i32 + dx;
+                        let ny = y as i32 + dy;
+                        if 0 <= nx && nx < WIDTH as i32 && 0 <= ny && ny < HEIGHT as i32 {
+                            sum += get(nx as usize, ny as usize);
+                            n += 1.0;
+                        }
+                    }
+                    *out = sum / n;
+                }
+            });
+        }
+    });
+    std::mem::swap(&mut grid.current, &mut grid.next);
+}
+
+fn log_system(dt: Res<DeltaTime>, mut timer: ResMut<LogTimer>, grid: Res<Grid>) {
+    timer.0.update(dt.0);
+    if timer.0.just_finished() {
+        let center = grid.current[HEIGHT / 2 * WIDTH + WIDTH / 2];
+        info!(center, "Heat at the center");
+    }
+}
+
+struct GamePlugin;
+
+impl Plugin for GamePlugin {
+    fn build(self, app: &mut App) {
+        let mut current = vec![0.0; WIDTH * HEIGHT];
+        current[HEIGHT / 2 * WIDTH + WIDTH / 2] = 1_000_000.0;
+        app.insert_resource(Grid {
+            next: current.clone(),
+            current,
+        });
+        app.insert_resource(LogTimer(Timer::new(Duration::from_secs(1), true)));
+        app.with_stage(brengin::Stage::Update, |s| {
+            s.add_system(diffuse_system)
+                .add_system(log_system.after(diffuse_system));
+        });
+    }
+}
+
+async fn game() {
+    let mut app = App::default();
+    app.add_plugin(DefaultPlugins);
+    app.add_plugin(GamePlugin);
+    app.run().await.unwrap();
+}
+
+fn main() {
+    tracing_subscriber::fmt::init();
+    pollster::block_on(game());
+}
@@ -0,0 +1,87 @@
+use brengin::camera::{orthographic_camera_bundle, OrthographicCamera, PixelPerfect, WindowCamera};
+use brengin::prelude::*;
+use brengin::renderer::sprite_renderer::{self, SpriteSheet};
+use brengin::{App, DefaultPlugins, Plugin};
+use glam::Vec3;
+
+struct GamePlugin;
+
+/// Every texel of the sprites covers this many screen pixels
+const SCALE: u32 = 4;
+
+struct Wobble {
+    phase: f32,
+}
+
+fn setup(mut cmd: Commands, mut assets: ResMut<Assets<SpriteSheet>>) {
+    cmd.spawn()
+        .insert(WindowCamera)
+        .insert(PixelPerfect { scale: SCALE })
+        .insert_bundle(orthographic_camera_bundle(OrthographicCamera {
+            eye: Vec3::new(0.0, 0.0, -10.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            height: 180.0,
+            aspect: 16.0 / 9.0,
+            znear: 0.0,
+            zfar: 100.0,
+        }))
+        .insert_bundle(transform_bundle(Transform::default()));
+
+    let boid = assets
+        .insert_from_bytes(include_bytes!("assets/boid.png"))
+        .expect("Failed to load sprite");
+
+    for i in 0..5 {
+        // boid.png is 32x32 pixels, so 1 world unit is 1 texel
+        let mut tr = Transform::from_position(Vec3::new((i as f32 - 2.0) * 40.0, 0.0, 0.0));
+        tr.scale = Vec3::splat(32.0);
+        cmd.spawn()
+            .insert_bundle(transform_bundle(tr))
+            .insert_bundle(sprite_renderer::sprite_sheet_bundle(boid.clone(), None))
+            .insert(Wobble { phase: i as f32 });
+    }
+}
+
+struct Clock(f32);
+
+fn clock_system(dt: Res<DeltaTime>, mut clock: ResMut<Clock>) {
+    clock.0 += dt.0.as_secs_f32();
+}
+
+/// Slow, sub-pixel movement would shimmer without [PixelPerfect]
+fn pan_camera_system(clock: Res<Clock>, mut q: Query<&mut Transform, With<WindowCamera>>) {
+    for tr in q.iter_mut() {
+        tr.pos.x = (clock.0 * 0.3).sin() * 20.0;
+    }
+}
+
+fn wobble_system(clock: Res<Clock>, mut q: Query<(&mut Transform, &Wobble)>) {
+    for (tr, wobble) in q.iter_mut() {
+        tr.pos.y = (clock.0 + wobble.phase).sin() * 10.0;
+    }
+}
+
+impl Plugin for GamePlugin {
+    fn build(self, app: &mut brengin::App) {
+        app.add_startup_system(setup);
+        app.insert_resource(Clock(0.0));
+        app.with_stage(brengin::Stage::Update, |s| {
+            s.add_system(clock_system)
+                .add_system(pan_camera_system.after(clock_system))
+                .add_system(wobble_system.after(clock_system));
+        });
+    }
+}
+
+async fn game() {
+    let mut app = App::default();
+    app.add_plugin(DefaultPlugins);
+    app.add_plugin(GamePlugin);
+    app.run().await.unwrap();
+}
+
+fn main() {
+    tracing_subscriber::fmt::init();
+    pollster::block_on(game());
+}
@@ -0,0 +1,109 @@
+//! Renders 100k sprites and reports the render world's `Update` stage time, where sprite
+//! instances are gathered. Alternates between the parallel and the single threaded gather, to
+//! compare the two.
+//!
+//! `cargo run --release --example sprite_stress --features profile`
+use std::time::Duration;
+
+use brengin::camera::{orthographic_camera_bundle, OrthographicCamera, WindowCamera};
+use brengin::prelude::*;
+use brengin::renderer::sprite_renderer::{self, SpriteSheet};
+use brengin::{App, DefaultPlugins, Plugin, RenderStageTimings, Stage};
+use glam::Vec3;
+use tracing::info;
+
+const N: usize = 100_000;
+/// Measure each gather mode for this long before switching
+const SAMPLE_DURATION: Duration = Duration::from_secs(5);
+
+struct GamePlugin;
+
+#[derive(Default)]
+struct Samples {
+    timer: Option<Timer>,
+    total: Duration,
+    count: u32,
+}
+
+fn setup(mut cmd: Commands, mut assets: ResMut<Assets<SpriteSheet>>) {
+    cmd.spawn()
+        .insert(WindowCamera)
+        .insert_bundle(orthographic_camera_bundle(OrthographicCamera {
+            eye: Vec3::new(0.0, 0.0, -10.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            height: 1000.0,
+            aspect: 16.0 / 9.0,
+            znear: 0.0,
+            zfar: 100.0,
+        }))
+        .insert_bundle(transform_bundle(Transform::default()));
+
+    let boid = assets
+        .insert_from_bytes(include_bytes!("assets/boid.png"))
+        .expect("Failed to load sprite");
+
+    for _ in 0..N {
+        let mut tr = Transform::from_position(Vec3::new(
+            (fastrand::f32() - 0.5) * 1600.0,
+            (fastrand::f32() - 0.5) * 900.0,
+            0.0,
+        ));
+        tr.scale = Vec3::splat(8.0);
+        cmd.spawn()
+            .insert_bundle(transform_bundle(tr))
+            .insert_bundle(sprite_renderer::sprite_sheet_bundle(boid.clone(), None));
+    }
+}
+
+fn measure_system(
+    dt: Res<DeltaTime>,
+    timings: Option<Res<RenderStageTimings>>,
+    mut samples: ResMut<Samples>,
+    mut config: ResMut<ParallelConfig>,
+) {
+    let Some(update) = timings.and_then(|t| t.0 .0.get(&Stage::Update).copied()) else {
+        return;
+    };
+    samples.total += update;
+    samples.count += 1;
+    let timer = samples
+        .timer
+        .get_or_insert_with(|| Timer::new(SAMPLE_DURATION, true));
+    timer.update(dt.0);
+    if !timer.just_finished() {
+        return;
+    }
+    info!(
+        sprites = N,
+        single_threaded = config.single_threaded,
+        mean_render_update = ?samples.total / samples.count.max(1),
+        "Sprite gather"
+    );
+    samples.total = Duration::ZERO;
+    samples.count = 0;
+    config.single_threaded = !config.single_threaded;
+}
+
+impl Plugin for GamePlugin {
+    fn build(self, app: &mut App) {
+        app.insert_resource(ParallelConfig::default());
+        app.insert_resource(Samples::default());
+        app.add_startup_system(setup);
+        app.with_stage(Stage::Update, |s| {
+            s.add_system(measure_system);
+        });
+    }
+}
+
+async fn game() {
+    let mut app = App::default();
+    app.add_plugin(DefaultPlugins);
+    app.add_plugin(GamePlugin);
+    app.run().await.unwrap();
+}
+
+fn main() {
+    tracing_subscriber::fmt::init();
+    pollster::block_on(game());
+}
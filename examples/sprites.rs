@@ -1,10 +1,15 @@
 use std::time::Duration;
 
-use brengin::camera::{camera_bundle, PerspectiveCamera, WindowCamera};
+use brengin::camera::controllers::{CameraController, CameraControllerPlugin};
+use brengin::camera::{camera_bundle, CameraUniform, PerspectiveCamera, WindowCamera};
 use brengin::prelude::*;
-use brengin::renderer::sprite_renderer::{self, SpriteInstance, SpriteSheet};
-use brengin::{App, DefaultPlugins, Plugin};
-use glam::{Quat, Vec2, Vec3};
+use brengin::renderer::sprite_renderer::{
+    self, SpriteInstance, SpriteMaterial, SpriteSheet, SpriteSheetGrid,
+};
+use brengin::renderer::{BlendMode, RenderStats, WindowSize};
+use brengin::winit::event::MouseButton;
+use brengin::{App, DefaultPlugins, MouseInputs, Plugin};
+use glam::{Vec2, Vec3};
 use tracing::info;
 
 struct GamePlugin;
@@ -21,24 +26,75 @@ fn animation_system(dt: Res<DeltaTime>, mut q: Query<(&mut SpriteInstance, &mut
     });
 }
 
-fn camera_rotation_system(dt: Res<DeltaTime>, mut q: Query<&mut Transform, With<WindowCamera>>) {
-    for tr in q.iter_mut() {
-        tr.rot = tr
-            .rot
-            .mul_quat(Quat::from_rotation_y(
-                std::f32::consts::TAU / 8.0 * dt.0.as_secs_f32(),
-            ))
-            .normalize();
-        if !tr.rot.is_normalized() {
-            tr.rot = Quat::default();
-        }
+struct StatsTimer(Timer);
+
+/// There is no text rendering yet, so the stats are logged instead of drawn
+fn render_stats_system(
+    dt: Res<DeltaTime>,
+    mut timer: ResMut<StatsTimer>,
+    stats: Option<Res<RenderStats>>,
+) {
+    timer.0.update(dt.0);
+    if let (true, Some(stats)) = (timer.0.just_finished(), stats) {
+        info!(
+            draw_calls = stats.draw_calls,
+            instances = stats.instances,
+            pipeline_switches = stats.pipeline_switches,
+            bind_group_switches = stats.bind_group_switches,
+            "Render stats"
+        );
+    }
+}
+
+/// Clicking a sprite doubles its size
+fn click_to_select_system(
+    mouse: Res<MouseInputs>,
+    window: Option<Res<WindowSize>>,
+    cameras: Query<&CameraUniform, With<WindowCamera>>,
+    sprites: Query<(EntityId, &GlobalTransform, &SpriteInstance)>,
+    mut transforms: Query<(EntityId, &mut Transform), With<SpriteInstance>>,
+) {
+    if !mouse.just_pressed.contains(&MouseButton::Left) {
+        return;
+    }
+    let (Some(window), Some(camera)) = (window, cameras.iter().next()) else {
+        return;
+    };
+    let window = Vec2::new(window.width as f32, window.height as f32);
+    let ray = camera.screen_to_ray(mouse.cursor_position, window);
+    let Some(selected) = sprite_renderer::pick_sprite(&ray, camera, sprites.iter()) else {
+        return;
+    };
+    info!(?selected, "Selected sprite");
+    if let Some((_, tr)) = transforms.iter_mut().find(|(id, _)| *id == selected) {
+        tr.scale *= 2.0;
     }
 }
 
-fn setup(mut cmd: Commands, mut assets: ResMut<Assets<SpriteSheet>>) {
+const TINT_SHADER: &str = "
+@group(1) @binding(0)
+var texture: texture_2d<f32>;
+@group(1) @binding(1)
+var texture_sampler: sampler;
+
+@group(3) @binding(0)
+var<uniform> tint: vec4<f32>;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return textureSample(texture, texture_sampler, in.uv) * tint;
+}
+";
+
+fn setup(
+    mut cmd: Commands,
+    mut assets: ResMut<Assets<SpriteSheet>>,
+    mut materials: ResMut<Assets<SpriteMaterial>>,
+) {
     //camera
     cmd.spawn()
         .insert(WindowCamera)
+        .insert(CameraController::orbit())
         .insert_bundle(camera_bundle(PerspectiveCamera {
             eye: Vec3::new(0.0, 0.0, 50.0),
             target: Vec3::ZERO,
@@ -50,21 +106,33 @@ fn setup(mut cmd: Commands, mut assets: ResMut<Assets<SpriteSheet>>) {
         }))
         .insert_bundle(transform_bundle(Transform::default()));
 
-    let boom = load_sprite_sheet(
-        include_bytes!("assets/boom3.png"),
-        Vec2::splat(128.0),
-        8,
-        &mut assets,
+    let boom = assets
+        .insert_from_bytes_with(
+            include_bytes!("assets/boom3.png"),
+            &SpriteSheetGrid {
+                cols: 8,
+                rows: 8,
+                padding: Vec2::ZERO,
+            },
+        )
+        .expect("Failed to load spritesheet");
+
+    // every 10th explosion is tinted green and glows
+    let tint = materials.insert(
+        SpriteMaterial::new(TINT_SHADER)
+            .with_uniform(&[0.2f32, 1.0, 0.2, 1.0])
+            .with_blend(BlendMode::Additive),
     );
 
     const CUBE_SIDE: f32 = 100.0;
     info!("Spawning {N} explosions");
-    for _ in 0..N {
+    for i in 0..N {
         let x = fastrand::f32() * CUBE_SIDE - CUBE_SIDE / 2.0;
         let y = fastrand::f32() * CUBE_SIDE - CUBE_SIDE / 2.0;
         let z = fastrand::f32() * CUBE_SIDE - CUBE_SIDE / 2.0;
 
-        cmd.spawn()
+        let mut sprite = cmd.spawn();
+        sprite
             .insert_bundle(transform_bundle(Transform::from_position(Vec3::new(
                 x, y, z,
             ))))
@@ -73,27 +141,21 @@ fn setup(mut cmd: Commands, mut assets: ResMut<Assets<SpriteSheet>>) {
                 Duration::from_secs_f32(fastrand::f32() / 30.0),
                 true,
             ));
+        if i % 10 == 0 {
+            sprite.insert(tint.clone());
+        }
     }
 }
 
-fn load_sprite_sheet(
-    bytes: &[u8],
-    box_size: Vec2,
-    num_cols: u32,
-    assets: &mut Assets<SpriteSheet>,
-) -> Handle<SpriteSheet> {
-    let image = image::load_from_memory(bytes).expect("Failed to load spritesheet");
-    let sprite_sheet = SpriteSheet::from_image(Vec2::ZERO, box_size, num_cols, image);
-
-    assets.insert(sprite_sheet)
-}
-
 impl Plugin for GamePlugin {
     fn build(self, app: &mut brengin::App) {
+        app.add_plugin(CameraControllerPlugin);
         app.add_startup_system(setup);
+        app.insert_resource(StatsTimer(Timer::new(Duration::from_secs(1), true)));
         app.with_stage(brengin::Stage::Update, |s| {
             s.add_system(animation_system)
-                .add_system(camera_rotation_system);
+                .add_system(click_to_select_system)
+                .add_system(render_stats_system);
         });
     }
 }
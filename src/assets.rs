@@ -1,6 +1,7 @@
+use anyhow::Context;
 use cecs::{prelude::*, Component};
 use std::{
-    collections::HashMap,
+    collections::{BTreeMap, HashMap},
     marker::PhantomData,
     ptr::NonNull,
     sync::atomic::{AtomicUsize, Ordering},
@@ -16,6 +17,11 @@ struct RefCount {
     weak_references: AtomicUsize,
 }
 
+/// Strong reference to an asset. Assets are kept alive as long as at least one `Handle` points
+/// to them, and are collected by the asset GC once the last `Handle` is dropped.
+///
+/// Prefer [WeakHandle] for references that should not keep the asset alive, e.g. caches,
+/// otherwise the asset leaks for as long as the cache holds on to it.
 pub struct Handle<T> {
     id: AssetId,
     weak: WeakHandle<T>,
@@ -27,6 +33,10 @@ impl<T> Default for Handle<T> {
     }
 }
 
+/// Reference to an asset that does not keep it alive. Obtain a [Handle] via
+/// [WeakHandle::upgrade] to access the asset, which fails if the asset was already collected.
+///
+/// `WeakHandle`s are cheap to clone and can be stored in components.
 pub struct WeakHandle<T> {
     id: AssetId,
     references: NonNull<RefCount>,
@@ -38,6 +48,7 @@ impl<T> WeakHandle<T> {
         unsafe { self.references.as_ref() }
     }
 
+    /// Returns `None` if all strong handles of the asset have been dropped
     pub fn upgrade(&self) -> Option<Handle<T>> {
         let mut n = self.data().data_references.load(Ordering::Relaxed);
         loop {
@@ -143,9 +154,30 @@ impl<T> Handle<T> {
     }
 }
 
+/// Assets that can be decoded from raw bytes, e.g. files embedded via `include_bytes!`
+pub trait DecodeAsset: Sized {
+    /// Additional information needed to decode the asset
+    type Settings: Default;
+
+    fn decode(bytes: &[u8], settings: &Self::Settings) -> anyhow::Result<Self>;
+}
+
+impl DecodeAsset for image::DynamicImage {
+    type Settings = ();
+
+    fn decode(bytes: &[u8], _settings: &Self::Settings) -> anyhow::Result<Self> {
+        let image = image::load_from_memory(bytes)?;
+        Ok(image)
+    }
+}
+
 pub struct Assets<T> {
     assets: HashMap<AssetId, AssetEntry<T>>,
     next_id: AssetId,
+    /// `start -> end` (exclusive) ranges of ids below `next_id` that were skipped by
+    /// [Assets::insert_with_id] and may still be claimed. Any other id below `next_id` is live
+    /// or belonged to a removed asset, whose handles may still be around.
+    unclaimed: BTreeMap<AssetId, AssetId>,
 }
 
 impl<T> Default for Assets<T> {
@@ -153,6 +185,7 @@ impl<T> Default for Assets<T> {
         Self {
             assets: Default::default(),
             next_id: 0,
+            unclaimed: Default::default(),
         }
     }
 }
@@ -161,6 +194,57 @@ impl<T> Assets<T> {
     pub fn insert(&mut self, val: T) -> Handle<T> {
         let id = self.next_id;
         self.next_id += 1;
+        self.insert_entry(id, val)
+    }
+
+    /// Insert the asset with a caller-provided id, e.g. one stored in a save-game.
+    ///
+    /// Fails if the id is in use, or was used by an asset that has since been removed or
+    /// collected. [AssetId::MAX] is reserved for default handles.
+    /// Subsequent [Assets::insert] calls never return an id at or below the given one.
+    pub fn insert_with_id(&mut self, id: AssetId, val: T) -> anyhow::Result<Handle<T>> {
+        if id == AssetId::MAX {
+            anyhow::bail!("Asset id {id} is reserved");
+        }
+        if self.assets.contains_key(&id) {
+            anyhow::bail!(
+                "Asset id {id} is already in use by another {}",
+                std::any::type_name::<T>()
+            );
+        }
+        if id >= self.next_id {
+            if id > self.next_id {
+                self.unclaimed.insert(self.next_id, id);
+            }
+            self.next_id = id + 1;
+        } else if !self.claim(id) {
+            anyhow::bail!(
+                "Asset id {id} belonged to a removed {}, ids are never reused",
+                std::any::type_name::<T>()
+            );
+        }
+        Ok(self.insert_entry(id, val))
+    }
+
+    /// Remove `id` from the unclaimed ranges, returns whether it was unclaimed
+    fn claim(&mut self, id: AssetId) -> bool {
+        let Some((&start, &end)) = self.unclaimed.range(..=id).next_back() else {
+            return false;
+        };
+        if id >= end {
+            return false;
+        }
+        self.unclaimed.remove(&start);
+        if start < id {
+            self.unclaimed.insert(start, id);
+        }
+        if id + 1 < end {
+            self.unclaimed.insert(id + 1, end);
+        }
+        true
+    }
+
+    fn insert_entry(&mut self, id: AssetId, val: T) -> Handle<T> {
         let handle = Handle::new(id);
         let _old = self.assets.insert(
             id,
@@ -178,6 +262,42 @@ impl<T> Assets<T> {
         handle
     }
 
+    /// Remove the asset immediately, regardless of the remaining [Handle]s.
+    ///
+    /// Outstanding handles become dangling: [Assets::get] panics on them, use
+    /// [Assets::get_by_id] to check. Render-side resources mirroring the asset (e.g. sprite sheet
+    /// textures) are released on the next extraction.
+    pub fn remove(&mut self, id: AssetId) -> Option<T> {
+        let entry = self.assets.remove(&id)?;
+        debug!(
+            id = tracing::field::debug(id),
+            ty = std::any::type_name::<T>(),
+            strong_count = entry.strong_count(),
+            "Removed asset"
+        );
+        Some(entry.val)
+    }
+
+    pub fn insert_from_bytes(&mut self, bytes: &[u8]) -> anyhow::Result<Handle<T>>
+    where
+        T: DecodeAsset,
+    {
+        self.insert_from_bytes_with(bytes, &Default::default())
+    }
+
+    pub fn insert_from_bytes_with(
+        &mut self,
+        bytes: &[u8],
+        settings: &T::Settings,
+    ) -> anyhow::Result<Handle<T>>
+    where
+        T: DecodeAsset,
+    {
+        let val = T::decode(bytes, settings)
+            .with_context(|| format!("Failed to decode asset {}", std::any::type_name::<T>()))?;
+        Ok(self.insert(val))
+    }
+
     pub fn iter(&self) -> impl Iterator<Item = (AssetId, &T)> {
         self.assets.iter().map(|(id, entry)| (*id, &entry.val))
     }
@@ -188,6 +308,44 @@ impl<T> Assets<T> {
             .map(|(id, entry)| (*id, &mut entry.val))
     }
 
+    pub fn len(&self) -> usize {
+        self.assets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.assets.is_empty()
+    }
+
+    /// Number of live [Handle]s pointing to the asset. Assets with 0 strong references are
+    /// collected by the next GC.
+    ///
+    /// Returns 0 if the asset does not exist
+    pub fn strong_count(&self, id: AssetId) -> usize {
+        self.assets
+            .get(&id)
+            .map(|entry| entry.strong_count())
+            .unwrap_or(0)
+    }
+
+    /// Returns a weak reference to the asset, without affecting its lifetime
+    pub fn get_weak(&self, id: AssetId) -> Option<WeakHandle<T>> {
+        self.assets.get(&id).map(|entry| entry.handle.clone())
+    }
+
+    /// Returns a new strong reference to the asset, if it's still alive
+    pub fn get_handle(&self, id: AssetId) -> Option<Handle<T>> {
+        self.assets
+            .get(&id)
+            .and_then(|entry| entry.handle.upgrade())
+    }
+
+    /// Iterate over the assets and their strong reference counts
+    pub fn iter_with_counts(&self) -> impl Iterator<Item = (AssetId, &T, usize)> {
+        self.assets
+            .iter()
+            .map(|(id, entry)| (*id, &entry.val, entry.strong_count()))
+    }
+
     pub fn contains(&self, id: AssetId) -> bool {
         self.assets.contains_key(&id)
     }
@@ -200,6 +358,17 @@ impl<T> Assets<T> {
         self.assets.get_mut(&id).map(|val| &mut val.val)
     }
 
+    /// Returns `None` if the asset has been removed
+    pub fn try_get(&self, handle: &Handle<T>) -> Option<&T> {
+        self.get_by_id(handle.id)
+    }
+
+    /// Returns `None` if the asset has been removed
+    pub fn try_get_mut(&mut self, handle: &Handle<T>) -> Option<&mut T> {
+        self.get_by_id_mut(handle.id)
+    }
+
+    /// Panics if the asset has been removed
     pub fn get(&self, handle: &Handle<T>) -> &T {
         self.assets
             .get(&handle.id)
@@ -207,6 +376,7 @@ impl<T> Assets<T> {
             .expect("Handle was invalid")
     }
 
+    /// Panics if the asset has been removed
     pub fn get_mut(&mut self, handle: &Handle<T>) -> &mut T {
         self.assets
             .get_mut(&handle.id)
@@ -220,10 +390,14 @@ struct AssetEntry<T> {
     handle: WeakHandle<T>,
 }
 
+impl<T> AssetEntry<T> {
+    fn strong_count(&self) -> usize {
+        self.handle.data().data_references.load(Ordering::Relaxed)
+    }
+}
+
 fn gc_assets<T: 'static>(mut assets: ResMut<Assets<T>>) {
-    assets
-        .assets
-        .retain(|_id, val| val.handle.data().data_references.load(Ordering::Relaxed) > 0);
+    assets.assets.retain(|_, val| val.strong_count() > 0);
 }
 
 pub struct AssetsPlugin<T> {
@@ -244,3 +418,137 @@ impl<T: Component> Plugin for AssetsPlugin<T> {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_strong_count() {
+        let mut assets = Assets::<i32>::default();
+        assert!(assets.is_empty());
+
+        let a = assets.insert(42);
+        let id = a.id();
+        assert_eq!(assets.len(), 1);
+        assert_eq!(assets.strong_count(id), 1);
+
+        let b = a.clone();
+        assert_eq!(assets.strong_count(id), 2);
+
+        let weak = b.downgrade();
+        assert_eq!(assets.strong_count(id), 2);
+
+        drop(a);
+        assert_eq!(assets.strong_count(id), 1);
+        let counts = assets.iter_with_counts().collect::<Vec<_>>();
+        assert_eq!(counts, vec![(id, &42, 1)]);
+
+        let weak = assets.get_weak(id).unwrap();
+        assert_eq!(weak.id(), id);
+        assert_eq!(assets.strong_count(id), 1);
+
+        drop(b);
+        assert_eq!(assets.strong_count(id), 0);
+        assert!(assets.get_handle(id).is_none());
+        assert!(weak.upgrade().is_none());
+
+        let mut world = World::new(1);
+        world.insert_resource(assets);
+        world.run_system(gc_assets::<i32>).unwrap();
+        let assets = world.get_resource::<Assets<i32>>().unwrap();
+        assert!(assets.is_empty());
+        assert_eq!(assets.strong_count(id), 0);
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut assets = Assets::<i32>::default();
+        let a = assets.insert(42);
+
+        assert_eq!(assets.remove(a.id()), Some(42));
+        assert!(!assets.contains(a.id()));
+        assert!(assets.get_by_id(a.id()).is_none());
+        assert!(assets.try_get(&a).is_none());
+        assert!(assets.try_get_mut(&a).is_none());
+        assert_eq!(assets.remove(a.id()), None);
+
+        // handles outliving the asset are still safe to drop
+        let b = a.clone();
+        drop(a);
+        drop(b);
+    }
+
+    #[test]
+    fn test_insert_with_id() {
+        let mut assets = Assets::<i32>::default();
+        let a = assets.insert_with_id(5, 42).unwrap();
+        assert_eq!(a.id(), 5);
+        assert_eq!(assets.get(&a), &42);
+
+        assert!(assets.insert_with_id(5, 1).is_err());
+        assert!(assets.insert_with_id(AssetId::MAX, 1).is_err());
+        assert_eq!(assets.get(&a), &42);
+
+        let b = assets.insert(2);
+        assert_eq!(b.id(), 6);
+
+        // unused ids below next_id may still be claimed explicitly
+        let c = assets.insert_with_id(0, 3).unwrap();
+        assert_eq!(c.id(), 0);
+        assert_eq!(assets.insert(4).id(), 7);
+
+        // handles of removed assets must not resolve to a new asset
+        assets.remove(a.id());
+        assert!(assets.insert_with_id(a.id(), 5).is_err());
+        assert!(assets.try_get(&a).is_none());
+
+        // neither may those of collected ones
+        drop(b);
+        let mut world = World::new(1);
+        world.insert_resource(assets);
+        world.run_system(gc_assets::<i32>).unwrap();
+        let assets = world.get_resource_mut::<Assets<i32>>().unwrap();
+        assert!(assets.insert_with_id(6, 6).is_err());
+
+        // the skipped ids 1..5 can be claimed in any order, exactly once
+        assert!(assets.insert_with_id(3, 0).is_ok());
+        assert!(assets.insert_with_id(3, 0).is_err());
+        assert!(assets.insert_with_id(1, 0).is_ok());
+        assert_eq!(assets.unclaimed.len(), 2);
+    }
+
+    #[test]
+    fn test_id_bookkeeping_is_bounded() {
+        let mut world = World::new(1);
+        world.insert_resource(Assets::<i32>::default());
+        for i in 0..10_000 {
+            world
+                .run_system(move |mut assets: ResMut<Assets<i32>>| {
+                    let a = assets.insert(i);
+                    let b = assets.insert_with_id(a.id() + 3, i).unwrap();
+                    let c = assets.insert_with_id(a.id() + 2, i).unwrap();
+                    let d = assets.insert_with_id(a.id() + 1, i).unwrap();
+                    assets.remove(c.id());
+                    drop((a, b, c, d));
+                })
+                .unwrap();
+            world.run_system(gc_assets::<i32>).unwrap();
+
+            let assets = world.get_resource::<Assets<i32>>().unwrap();
+            assert!(assets.is_empty());
+            // nothing is kept for removed or collected ids
+            assert!(assets.unclaimed.is_empty());
+        }
+        let assets = world.get_resource_mut::<Assets<i32>>().unwrap();
+        assert!(assets.insert_with_id(0, 0).is_err());
+        assert_eq!(assets.insert(0).id(), 40_000);
+    }
+
+    #[test]
+    fn test_insert_from_bytes_invalid() {
+        let mut assets = Assets::<image::DynamicImage>::default();
+        assert!(assets.insert_from_bytes(b"not an image").is_err());
+        assert!(assets.is_empty());
+    }
+}
@@ -1,7 +1,10 @@
+pub mod controllers;
+
 use cecs::prelude::*;
-use glam::{Mat4, Vec3, Vec4};
+use glam::{Mat4, Vec2, Vec3, Vec4};
 
 use crate::{
+    commands_ext::insert_missing,
     renderer::{ExtractionPlugin, GraphicsState, WindowSize},
     transform::GlobalTransform,
     Plugin, Stage,
@@ -22,30 +25,285 @@ pub struct PerspectiveCamera {
     pub zfar: f32,
 }
 
+pub struct OrthographicCamera {
+    pub eye: Vec3,
+    pub target: Vec3,
+    pub up: Vec3,
+    /// Height of the view in world units, the width is `height * aspect`
+    pub height: f32,
+    pub aspect: f32,
+    pub znear: f32,
+    pub zfar: f32,
+}
+
+/// Coordinate convention of a camera. Insert on the camera entity, cameras without the
+/// component are left-handed.
+///
+/// Frustum culling works on the final view-projection, so it is independent of the convention.
+/// Sprites are rendered without back-face culling, so their winding does not matter either.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Handedness {
+    /// `+Z` points into the screen
+    #[default]
+    Left,
+    /// `-Z` points into the screen
+    Right,
+}
+
+impl Handedness {
+    pub fn look_at(self, eye: Vec3, target: Vec3, up: Vec3) -> Mat4 {
+        match self {
+            Handedness::Left => Mat4::look_at_lh(eye, target, up),
+            Handedness::Right => Mat4::look_at_rh(eye, target, up),
+        }
+    }
+
+    pub fn perspective(self, fovy: f32, aspect: f32, znear: f32, zfar: f32) -> Mat4 {
+        match self {
+            Handedness::Left => Mat4::perspective_lh(fovy, aspect, znear, zfar),
+            Handedness::Right => Mat4::perspective_rh(fovy, aspect, znear, zfar),
+        }
+    }
+
+    pub fn orthographic(self, min: Vec2, max: Vec2, znear: f32, zfar: f32) -> Mat4 {
+        match self {
+            Handedness::Left => Mat4::orthographic_lh(min.x, max.x, min.y, max.y, znear, zfar),
+            Handedness::Right => Mat4::orthographic_rh(min.x, max.x, min.y, max.y, znear, zfar),
+        }
+    }
+}
+
+/// Snaps an [OrthographicCamera] to the pixel grid, so pixel art does not shimmer when the
+/// camera moves.
+///
+/// Combined with [WindowCamera] the view is sized to the window, such that 1 world unit covers
+/// `scale` pixels. The camera position is rounded to `1 / scale` world units.
+#[derive(Debug, Clone, Copy)]
+pub struct PixelPerfect {
+    pub scale: u32,
+}
+
+impl Default for PixelPerfect {
+    fn default() -> Self {
+        Self { scale: 1 }
+    }
+}
+
+impl PixelPerfect {
+    /// Size of a screen pixel in world units
+    pub fn texel_size(&self) -> f32 {
+        1.0 / self.scale.max(1) as f32
+    }
+
+    fn snap(&self, v: Vec2) -> Vec2 {
+        let texel = self.texel_size();
+        (v / texel).round() * texel
+    }
+}
+
+/// Shakes the camera on screen, without moving the camera in the game world.
+///
+/// The shake is applied to the rendered view only, gameplay and frustum culling use the
+/// unperturbed camera. Strength is proportional to the square of the trauma, which decays over
+/// time.
+///
+/// Trauma is added in the game world and decays in the render world, once per rendered frame,
+/// so the shake stays smooth regardless of the tick rate.
+#[derive(Debug, Clone, Copy)]
+pub struct CameraShake {
+    /// Trauma lost per second
+    pub decay: f32,
+    /// Largest translation offset at full trauma, in view space
+    pub max_offset: f32,
+    /// Largest roll at full trauma, in radians
+    pub max_roll: f32,
+    /// Sum of the trauma ever added. The render world applies the difference since the last
+    /// extraction.
+    added: f64,
+}
+
+impl Default for CameraShake {
+    fn default() -> Self {
+        Self {
+            decay: 1.0,
+            max_offset: 1.0,
+            max_roll: 0.1,
+            added: 0.0,
+        }
+    }
+}
+
+impl CameraShake {
+    /// Trauma is clamped to `[0, 1]` when it's applied
+    pub fn add_trauma(&mut self, amount: f32) {
+        self.added += amount.max(0.0) as f64;
+    }
+}
+
+/// Render world state of a [CameraShake]
+#[derive(Debug, Clone, Copy)]
+struct CameraShakeState {
+    /// In the range `[0, 1]`
+    trauma: f32,
+    /// Noise phase, advanced while shaking
+    phase: f32,
+    /// [CameraShake::added] already applied
+    applied: f64,
+    last_update: Option<instant::Instant>,
+}
+
+impl From<&CameraShake> for CameraShakeState {
+    fn from(_: &CameraShake) -> Self {
+        Self {
+            trauma: 0.0,
+            phase: 0.0,
+            applied: 0.0,
+            last_update: None,
+        }
+    }
+}
+
+impl CameraShakeState {
+    fn update(&mut self, shake: &CameraShake, dt: f32) {
+        let added = (shake.added - self.applied) as f32;
+        self.applied = shake.added;
+        self.trauma = (self.trauma + added).clamp(0.0, 1.0);
+        if self.trauma > 0.0 {
+            self.phase += dt;
+        }
+        self.trauma = (self.trauma - shake.decay * dt).max(0.0);
+    }
+
+    /// View space transformation to apply to the camera
+    fn offset(&self, shake: &CameraShake) -> Mat4 {
+        let strength = self.trauma * self.trauma;
+        if strength <= 0.0 {
+            return Mat4::IDENTITY;
+        }
+        let t = self.phase;
+        let offset = Vec3::new(noise(t, 0.0), noise(t, 1.0), 0.0) * shake.max_offset * strength;
+        let roll = noise(t, 2.0) * shake.max_roll * strength;
+        Mat4::from_rotation_z(roll) * Mat4::from_translation(offset)
+    }
+}
+
+/// Cheap smooth noise in `[-1, 1]`
+fn noise(t: f32, seed: f32) -> f32 {
+    let t = t + seed * 17.13;
+    ((t * 23.0).sin() + (t * 37.3).sin() * 0.5 + (t * 51.7).sin() * 0.25) / 1.75
+}
+
+/// Render world system, decays the shake by the time elapsed since the previous frame
+fn update_camera_shake(mut q: Query<(&CameraShake, &mut CameraShakeState)>) {
+    let now = instant::Instant::now();
+    for (shake, state) in q.iter_mut() {
+        let dt = state
+            .last_update
+            .map(|last| now.saturating_duration_since(last).as_secs_f32())
+            .unwrap_or(0.0);
+        state.last_update = Some(now);
+        state.update(shake, dt);
+    }
+}
+
+impl crate::renderer::Extract for CameraShake {
+    type QueryItem = &'static CameraShake;
+
+    type Filter = ();
+
+    type Out = (Self,);
+
+    fn extract<'a>(
+        it: <Self::QueryItem as cecs::query::QueryFragment>::Item<'a>,
+    ) -> Option<Self::Out> {
+        Some((*it,))
+    }
+}
+
 /// Cameras marked with this component are automatically updated to fit their window
 /// Camera entities do not have this component by default
 pub struct WindowCamera;
 
-fn update_camera_aspect(
+/// Fits [WindowCamera]s to the window, in `PreUpdate`
+pub fn update_camera_aspect(
     gs: Res<WindowSize>,
     mut q: Query<&mut PerspectiveCamera, With<WindowCamera>>,
+    mut ortho: Query<(&mut OrthographicCamera, Option<&PixelPerfect>), With<WindowCamera>>,
 ) {
     let size = *gs;
+    if size.width == 0 || size.height == 0 {
+        // minimized
+        return;
+    }
     let aspect = size.width as f32 / size.height as f32;
     for cam in q.iter_mut() {
         cam.aspect = aspect;
     }
+    for (cam, pixel) in ortho.iter_mut() {
+        cam.aspect = aspect;
+        if let Some(pixel) = pixel {
+            cam.height = size.height as f32 * pixel.texel_size();
+        }
+    }
 }
 
 impl PerspectiveCamera {
     pub fn view_projection(&self) -> Mat4 {
-        let view = Mat4::look_at_lh(self.eye, self.target, self.up);
-        let proj = Mat4::perspective_lh(self.fovy, self.aspect, self.znear, self.zfar);
+        self.view_projection_with(Handedness::Left)
+    }
+
+    pub fn view_projection_with(&self, handedness: Handedness) -> Mat4 {
+        let view = handedness.look_at(self.eye, self.target, self.up);
+        let proj = handedness.perspective(self.fovy, self.aspect, self.znear, self.zfar);
+
+        proj * view
+    }
+}
+
+impl OrthographicCamera {
+    pub fn view_projection(&self) -> Mat4 {
+        self.view_projection_impl(None, Handedness::Left)
+    }
+
+    pub fn view_projection_with(&self, handedness: Handedness) -> Mat4 {
+        self.view_projection_impl(None, handedness)
+    }
+
+    fn view_projection_impl(&self, pixel: Option<&PixelPerfect>, handedness: Handedness) -> Mat4 {
+        let mut eye = self.eye;
+        let mut target = self.target;
+        let size = Vec2::new(self.height * self.aspect, self.height);
+        let mut min = size * -0.5;
+        if let Some(pixel) = pixel {
+            // move the target with the eye, so the view direction is unchanged
+            let offset = (pixel.snap(eye.truncate()) - eye.truncate()).extend(0.0);
+            eye += offset;
+            target += offset;
+            // odd window sizes would put the pixel centers on the grid, not their corners
+            let texel = pixel.texel_size();
+            min = (min / texel).floor() * texel;
+        }
+        let max = min + size;
+        let view = handedness.look_at(eye, target, self.up);
+        let proj = handedness.orthographic(min, max, self.znear, self.zfar);
 
         proj * view
     }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    /// Normalized direction
+    pub direction: Vec3,
+}
+
+impl Ray {
+    pub fn at(&self, t: f32) -> Vec3 {
+        self.origin + self.direction * t
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
@@ -67,6 +325,56 @@ impl Default for CameraUniform {
 }
 
 impl CameraUniform {
+    /// Screen space camera, mapping `(0, 0)` to the top-left and `(width, height)` to the
+    /// bottom-right corner of the window
+    pub fn screen_space(width: u32, height: u32) -> Self {
+        let proj = Mat4::orthographic_lh(0.0, width as f32, height as f32, 0.0, 0.0, 1.0);
+        Self {
+            view_proj: proj,
+            view: Mat4::IDENTITY,
+            proj,
+            view_inv: Mat4::IDENTITY,
+        }
+    }
+
+    /// Ray through the given point of the window, in world space.
+    ///
+    /// `cursor` is in physical pixels relative to the top-left corner, e.g.
+    /// [crate::MouseInputs::cursor_position]. Works for both perspective and orthographic
+    /// projections, the latter producing parallel rays.
+    pub fn screen_to_ray(&self, cursor: Vec2, window_size: Vec2) -> Ray {
+        let ndc = Vec2::new(
+            cursor.x / window_size.x * 2.0 - 1.0,
+            1.0 - cursor.y / window_size.y * 2.0,
+        );
+        let inv = self.view_proj.inverse();
+        let near = inv.project_point3(ndc.extend(0.0));
+        let far = inv.project_point3(ndc.extend(1.0));
+        Ray {
+            origin: near,
+            direction: (far - near).normalize(),
+        }
+    }
+
+    fn shaken(&self, offset: Mat4) -> Self {
+        let view = offset * self.view;
+        Self {
+            view,
+            view_inv: view.inverse(),
+            view_proj: self.proj * view,
+            proj: self.proj,
+        }
+    }
+
+    /// World space axes of the camera: `(right, up, forward)`
+    pub fn axes(&self) -> (Vec3, Vec3, Vec3) {
+        (
+            self.view_inv.col(0).truncate().normalize(),
+            self.view_inv.col(1).truncate().normalize(),
+            self.view_inv.col(2).truncate().normalize(),
+        )
+    }
+
     pub fn desc<'a>() -> wgpu::BindGroupLayoutDescriptor<'a> {
         wgpu::BindGroupLayoutDescriptor {
             entries: &[wgpu::BindGroupLayoutEntry {
@@ -84,13 +392,46 @@ impl CameraUniform {
     }
 }
 
-fn update_view_projections(
-    mut q: Query<(&GlobalTransform, &PerspectiveCamera, &mut CameraUniform)>,
+/// Computes the [CameraUniform] of [PerspectiveCamera]s, in `Update`.
+///
+/// Systems moving the camera in `Update` should run before it, otherwise the view lags a tick
+/// behind.
+pub fn update_view_projections(
+    mut q: Query<(
+        &GlobalTransform,
+        &PerspectiveCamera,
+        Option<&Handedness>,
+        &mut CameraUniform,
+    )>,
 ) {
-    for (tr, cam, uni) in q.iter_mut() {
+    for (tr, cam, handedness, uni) in q.iter_mut() {
         uni.view = tr.0.inverse().compute_matrix();
         uni.view_inv = uni.view.inverse();
-        uni.proj = cam.view_projection();
+        uni.proj = cam.view_projection_with(handedness.copied().unwrap_or_default());
+        uni.view_proj = uni.proj * uni.view;
+    }
+}
+
+/// Computes the [CameraUniform] of [OrthographicCamera]s, in `Update`
+pub fn update_orthographic_view_projections(
+    mut q: Query<(
+        &GlobalTransform,
+        &OrthographicCamera,
+        Option<&PixelPerfect>,
+        Option<&Handedness>,
+        &mut CameraUniform,
+    )>,
+) {
+    for (tr, cam, pixel, handedness, uni) in q.iter_mut() {
+        let mut tr = tr.0;
+        if let Some(pixel) = pixel {
+            let pos = pixel.snap(tr.pos.truncate());
+            tr.pos.x = pos.x;
+            tr.pos.y = pos.y;
+        }
+        uni.view = tr.inverse().compute_matrix();
+        uni.view_inv = uni.view.inverse();
+        uni.proj = cam.view_projection_impl(pixel, handedness.copied().unwrap_or_default());
         uni.view_proj = uni.proj * uni.view;
     }
 }
@@ -109,7 +450,11 @@ impl crate::renderer::Extract for CameraUniform {
     }
 }
 
-fn upload_camera_uniform(queue: &wgpu::Queue, buffer: &wgpu::Buffer, uni: &CameraUniform) {
+pub(crate) fn upload_camera_uniform(
+    queue: &wgpu::Queue,
+    buffer: &wgpu::Buffer,
+    uni: &CameraUniform,
+) {
     queue.write_buffer(
         &buffer,
         0,
@@ -136,14 +481,26 @@ fn insert_missing_camera_buffers(
 
 fn update_camera_buffers(
     renderer: Res<GraphicsState>,
-    q: Query<(&CameraUniform, &mut CameraBuffer)>,
+    q: Query<(
+        &CameraUniform,
+        &mut CameraBuffer,
+        Option<&CameraShake>,
+        Option<&CameraShakeState>,
+    )>,
 ) {
-    for (uni, CameraBuffer(buffer)) in q.iter() {
-        upload_camera_uniform(renderer.queue(), &buffer, uni);
+    for (uni, CameraBuffer(buffer), shake, state) in q.iter() {
+        match (shake, state) {
+            (Some(shake), Some(state)) => {
+                let uni = uni.shaken(state.offset(shake));
+                upload_camera_uniform(renderer.queue(), &buffer, &uni);
+            }
+            _ => upload_camera_uniform(renderer.queue(), &buffer, uni),
+        }
     }
 }
 
-fn update_frustum(mut q: Query<(&mut ViewFrustum, &CameraUniform)>) {
+/// Computes the [ViewFrustum] of cameras, in `Update`, after the view-projections
+pub fn update_frustum(mut q: Query<(&mut ViewFrustum, &CameraUniform)>) {
     for (fr, cam) in q.iter_mut() {
         let mat = &cam.view_proj;
         // left
@@ -190,14 +547,22 @@ impl Plugin for CameraPlugin {
         })
         .with_stage(Stage::Update, |s| {
             s.add_system(update_view_projections)
-                .add_system(update_frustum.after(update_view_projections));
+                .add_system(update_orthographic_view_projections)
+                .add_system(
+                    update_frustum
+                        .after(update_view_projections)
+                        .after(update_orthographic_view_projections),
+                );
         });
 
         app.add_plugin(ExtractionPlugin::<CameraUniform>::default());
+        app.add_plugin(ExtractionPlugin::<CameraShake>::default());
 
         app.render_app_mut().with_stage(Stage::Update, |s| {
             s.add_system(insert_missing_camera_buffers)
-                .add_system(update_camera_buffers);
+                .add_system(insert_missing::<CameraShake, CameraShakeState>)
+                .add_system(update_camera_shake)
+                .add_system(update_camera_buffers.after(update_camera_shake));
         });
     }
 }
@@ -205,3 +570,157 @@ impl Plugin for CameraPlugin {
 pub fn camera_bundle(camera: PerspectiveCamera) -> impl cecs::bundle::Bundle {
     (camera, CameraUniform::default(), ViewFrustum::default())
 }
+
+pub fn orthographic_camera_bundle(camera: OrthographicCamera) -> impl cecs::bundle::Bundle {
+    (camera, CameraUniform::default(), ViewFrustum::default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_screen_to_ray_perspective() {
+        let proj = Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 100.0);
+        let uni = CameraUniform {
+            view_proj: proj,
+            proj,
+            ..Default::default()
+        };
+
+        let ray = uni.screen_to_ray(Vec2::splat(50.0), Vec2::splat(100.0));
+        assert!(ray.origin.abs_diff_eq(Vec3::new(0.0, 0.0, 1.0), 1e-4));
+        assert!(ray.direction.abs_diff_eq(Vec3::Z, 1e-4));
+
+        // top-left corner, 90 degree fov
+        let ray = uni.screen_to_ray(Vec2::ZERO, Vec2::splat(100.0));
+        assert!(ray
+            .direction
+            .abs_diff_eq(Vec3::new(-1.0, 1.0, 1.0).normalize(), 1e-4));
+    }
+
+    #[test]
+    fn test_camera_shake() {
+        let mut shake = CameraShake::default();
+        let mut state = CameraShakeState::from(&shake);
+        assert_eq!(state.offset(&shake), Mat4::IDENTITY);
+
+        shake.add_trauma(0.7);
+        shake.add_trauma(0.7);
+        state.update(&shake, 0.0);
+        assert_eq!(state.trauma, 1.0);
+
+        state.update(&shake, 0.25);
+        assert_eq!(state.trauma, 0.75);
+        assert_ne!(state.offset(&shake), Mat4::IDENTITY);
+
+        // re-extracting the same shake does not add the trauma again
+        state.update(&shake, 0.25);
+        assert_eq!(state.trauma, 0.5);
+
+        shake.add_trauma(0.25);
+        state.update(&shake, 0.0);
+        assert_eq!(state.trauma, 0.75);
+    }
+
+    #[test]
+    fn test_screen_to_ray_orthographic() {
+        let proj = Mat4::orthographic_lh(-10.0, 10.0, -10.0, 10.0, 0.0, 100.0);
+        let uni = CameraUniform {
+            view_proj: proj,
+            proj,
+            ..Default::default()
+        };
+
+        let ray = uni.screen_to_ray(Vec2::new(0.0, 100.0), Vec2::splat(100.0));
+        assert!(ray.origin.abs_diff_eq(Vec3::new(-10.0, -10.0, 0.0), 1e-4));
+        assert!(ray.direction.abs_diff_eq(Vec3::Z, 1e-4));
+    }
+
+    fn ortho_camera(eye: Vec3) -> OrthographicCamera {
+        OrthographicCamera {
+            eye,
+            target: eye + Vec3::Z,
+            up: Vec3::Y,
+            height: 10.0,
+            aspect: 1.0,
+            znear: 0.0,
+            zfar: 100.0,
+        }
+    }
+
+    #[test]
+    fn test_pixel_perfect_sizes_to_window() {
+        let mut world = World::new(1);
+        world.insert_resource(WindowSize {
+            width: 101,
+            height: 100,
+        });
+        world
+            .run_system(|mut cmd: Commands| {
+                cmd.spawn()
+                    .insert(WindowCamera)
+                    .insert(PixelPerfect { scale: 2 })
+                    .insert(ortho_camera(Vec3::ZERO));
+            })
+            .unwrap();
+        world.run_system(update_camera_aspect).unwrap();
+
+        world.run_view_system(|q: Query<&OrthographicCamera>| {
+            let cam = q.iter().next().unwrap();
+            assert_eq!(cam.height, 50.0);
+            assert_eq!(cam.aspect, 1.01);
+        });
+    }
+
+    #[test]
+    fn test_pixel_perfect_snaps_eye() {
+        let pixel = PixelPerfect { scale: 2 };
+        let snapped = ortho_camera(Vec3::new(0.3, 0.2, -10.0))
+            .view_projection_impl(Some(&pixel), Handedness::Left);
+        let expected = ortho_camera(Vec3::new(0.5, 0.0, -10.0)).view_projection();
+
+        assert!(snapped.abs_diff_eq(expected, 1e-6));
+    }
+
+    #[test]
+    fn test_right_handed_camera() {
+        let cam = |eye: Vec3| PerspectiveCamera {
+            eye,
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            aspect: 1.0,
+            fovy: std::f32::consts::FRAC_PI_2,
+            znear: 1.0,
+            zfar: 100.0,
+        };
+        // both conventions see +X on the right, when looking into the screen
+        let lh = cam(Vec3::new(0.0, 0.0, -10.0)).view_projection();
+        let rh = cam(Vec3::new(0.0, 0.0, 10.0)).view_projection_with(Handedness::Right);
+        for view_proj in [lh, rh] {
+            let p = view_proj.project_point3(Vec3::X);
+            assert!(p.x > 0.0);
+            assert!((0.0..=1.0).contains(&p.z));
+        }
+
+        let mut world = World::new(1);
+        world
+            .run_system(move |mut cmd: Commands| {
+                cmd.spawn()
+                    .insert(ViewFrustum::default())
+                    .insert(CameraUniform {
+                        view_proj: rh,
+                        ..Default::default()
+                    });
+            })
+            .unwrap();
+        world.run_system(update_frustum).unwrap();
+        world.run_view_system(|q: Query<&ViewFrustum>| {
+            let fr = q.iter().next().unwrap();
+            let inside = |p: Vec3| fr.planes.iter().all(|pl| pl.dot(p.extend(1.0)) >= 0.0);
+            assert!(inside(Vec3::ZERO));
+            // behind the camera
+            assert!(!inside(Vec3::new(0.0, 0.0, 20.0)));
+        });
+    }
+}
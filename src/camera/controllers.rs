@@ -0,0 +1,247 @@
+use cecs::prelude::*;
+use glam::{Quat, Vec2, Vec3};
+use winit::event::MouseButton;
+
+use crate::{MouseInputs, Plugin, Stage};
+
+use super::{OrthographicCamera, PerspectiveCamera};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CameraControllerMode {
+    /// Pan and zoom only, the view direction is kept
+    Pan2d,
+    /// Pan, zoom and orbit around the camera's target
+    Orbit,
+}
+
+/// Moves the [PerspectiveCamera] or [OrthographicCamera] of the entity using the mouse
+///
+/// - middle-drag pans
+/// - wheel zooms, orthographic cameras zoom by changing their `height`
+/// - right-drag orbits, in [CameraControllerMode::Orbit] only
+///
+/// Requires [CameraControllerPlugin]
+#[derive(Debug, Clone, Copy)]
+pub struct CameraController {
+    pub mode: CameraControllerMode,
+    /// Pan per pixel, relative to the distance between the eye and the target, or to the height
+    /// of orthographic cameras
+    pub pan_speed: f32,
+    /// Zoom per scrolled pixel, relative to the distance between the eye and the target, or to
+    /// the height of orthographic cameras
+    pub zoom_speed: f32,
+    /// Radians per pixel
+    pub orbit_speed: f32,
+    /// Bounds of the distance between the eye and the target, or of the height of orthographic
+    /// cameras
+    pub min_distance: f32,
+    pub max_distance: f32,
+}
+
+impl CameraController {
+    pub fn new(mode: CameraControllerMode) -> Self {
+        Self {
+            mode,
+            pan_speed: 0.002,
+            zoom_speed: 0.002,
+            orbit_speed: 0.005,
+            min_distance: 1.0,
+            max_distance: 10_000.0,
+        }
+    }
+
+    pub fn pan_2d() -> Self {
+        Self::new(CameraControllerMode::Pan2d)
+    }
+
+    pub fn orbit() -> Self {
+        Self::new(CameraControllerMode::Orbit)
+    }
+
+    /// `scale` is the world size the pan speed is relative to
+    fn pan(&self, eye: &mut Vec3, target: &mut Vec3, up: Vec3, scale: f32, delta: Vec2) {
+        let forward = (*target - *eye).normalize();
+        let right = up.cross(forward).normalize();
+        let up = forward.cross(right);
+        // move the camera against the drag, so the scene follows the cursor
+        let d = (-right * delta.x + up * delta.y) * self.pan_speed * scale;
+        *eye += d;
+        *target += d;
+    }
+
+    fn zoom(&self, cam: &mut PerspectiveCamera, scroll: f32) {
+        let offset = cam.eye - cam.target;
+        let distance = (offset.length() * (1.0 - scroll * self.zoom_speed))
+            .clamp(self.min_distance, self.max_distance);
+        cam.eye = cam.target + offset.normalize() * distance;
+    }
+
+    fn zoom_ortho(&self, cam: &mut OrthographicCamera, scroll: f32) {
+        cam.height = (cam.height * (1.0 - scroll * self.zoom_speed))
+            .clamp(self.min_distance, self.max_distance);
+    }
+
+    fn orbit_by(&self, eye: &mut Vec3, target: Vec3, up: Vec3, delta: Vec2) {
+        let offset = *eye - target;
+        let yaw = Quat::from_axis_angle(up, delta.x * self.orbit_speed);
+        let offset = yaw * offset;
+
+        let right = up.cross(-offset).normalize();
+        let pitched = Quat::from_axis_angle(right, delta.y * self.orbit_speed) * offset;
+        // do not flip over the poles
+        let offset = if pitched.normalize().dot(up).abs() < 0.99 {
+            pitched
+        } else {
+            offset
+        };
+        *eye = target + offset;
+    }
+}
+
+fn update_camera_controllers(
+    mouse: Res<MouseInputs>,
+    mut perspective: Query<(&CameraController, &mut PerspectiveCamera)>,
+    mut ortho: Query<(&CameraController, &mut OrthographicCamera)>,
+) {
+    let delta = mouse.cursor_delta;
+    let scroll = mouse.scroll_delta.y;
+    let pan = mouse.pressed.contains(&MouseButton::Middle) && delta != Vec2::ZERO;
+    let orbit = |controller: &CameraController| {
+        controller.mode == CameraControllerMode::Orbit
+            && mouse.pressed.contains(&MouseButton::Right)
+            && delta != Vec2::ZERO
+    };
+    for (controller, cam) in perspective.iter_mut() {
+        if pan {
+            let distance = (cam.eye - cam.target).length();
+            controller.pan(&mut cam.eye, &mut cam.target, cam.up, distance, delta);
+        }
+        if scroll != 0.0 {
+            controller.zoom(cam, scroll);
+        }
+        if orbit(controller) {
+            controller.orbit_by(&mut cam.eye, cam.target, cam.up, delta);
+        }
+    }
+    for (controller, cam) in ortho.iter_mut() {
+        if pan {
+            let height = cam.height;
+            controller.pan(&mut cam.eye, &mut cam.target, cam.up, height, delta);
+        }
+        if scroll != 0.0 {
+            controller.zoom_ortho(cam, scroll);
+        }
+        if orbit(controller) {
+            controller.orbit_by(&mut cam.eye, cam.target, cam.up, delta);
+        }
+    }
+}
+
+pub struct CameraControllerPlugin;
+
+impl Plugin for CameraControllerPlugin {
+    fn build(self, app: &mut crate::App) {
+        app.with_stage(Stage::PreUpdate, |s| {
+            s.add_system(update_camera_controllers.after(crate::update_inputs));
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn camera() -> PerspectiveCamera {
+        PerspectiveCamera {
+            eye: Vec3::new(0.0, 0.0, 50.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            aspect: 1.0,
+            fovy: 1.0,
+            znear: 1.0,
+            zfar: 100.0,
+        }
+    }
+
+    fn ortho_camera() -> OrthographicCamera {
+        OrthographicCamera {
+            eye: Vec3::new(0.0, 0.0, -50.0),
+            target: Vec3::ZERO,
+            up: Vec3::Y,
+            height: 100.0,
+            aspect: 1.0,
+            znear: 1.0,
+            zfar: 100.0,
+        }
+    }
+
+    #[test]
+    fn test_pan_keeps_direction() {
+        let mut cam = camera();
+        CameraController::pan_2d().pan(
+            &mut cam.eye,
+            &mut cam.target,
+            cam.up,
+            50.0,
+            Vec2::new(10.0, 0.0),
+        );
+
+        assert_eq!(cam.eye - cam.target, Vec3::new(0.0, 0.0, 50.0));
+        assert_ne!(cam.target, Vec3::ZERO);
+        assert_eq!(cam.target.y, 0.0);
+    }
+
+    #[test]
+    fn test_zoom_is_clamped() {
+        let mut cam = camera();
+        let controller = CameraController::pan_2d();
+        controller.zoom(&mut cam, 100.0);
+        assert!((cam.eye.z - 40.0).abs() < 1e-4);
+
+        controller.zoom(&mut cam, 1e6);
+        assert_eq!(cam.eye.z, controller.min_distance);
+    }
+
+    #[test]
+    fn test_orbit_keeps_distance() {
+        let mut cam = camera();
+        CameraController::orbit().orbit_by(
+            &mut cam.eye,
+            cam.target,
+            cam.up,
+            Vec2::new(100.0, 50.0),
+        );
+
+        assert!(((cam.eye - cam.target).length() - 50.0).abs() < 1e-3);
+        assert_ne!(cam.eye, Vec3::new(0.0, 0.0, 50.0));
+    }
+
+    #[test]
+    fn test_ortho_camera() {
+        let mut world = World::new(1);
+        let mut mouse = MouseInputs::default();
+        mouse.pressed.insert(MouseButton::Middle);
+        mouse.cursor_delta = Vec2::new(10.0, 0.0);
+        mouse.scroll_delta = Vec2::new(0.0, 100.0);
+        world.insert_resource(mouse);
+        world
+            .run_system(|mut cmd: Commands| {
+                cmd.spawn()
+                    .insert(CameraController::pan_2d())
+                    .insert(ortho_camera());
+            })
+            .unwrap();
+        world.run_system(update_camera_controllers).unwrap();
+
+        world.run_view_system(|q: Query<&OrthographicCamera>| {
+            let cam = q.iter().next().unwrap();
+            // zooming changes the view size, not the distance
+            assert!((cam.height - 80.0).abs() < 1e-4);
+            assert_eq!(cam.eye - cam.target, Vec3::new(0.0, 0.0, -50.0));
+            // the camera moved sideways against the drag
+            assert_ne!(cam.target.x, 0.0);
+            assert_eq!(cam.target.y, 0.0);
+            assert_eq!(cam.target.z, 0.0);
+        });
+    }
+}
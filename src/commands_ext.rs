@@ -0,0 +1,52 @@
+use cecs::prelude::*;
+
+/// System inserting `T`, derived from `Source`, into every entity that has a `Source` but no `T`.
+///
+/// Register the instance for the component pair, e.g.
+/// `s.add_system(insert_missing::<Transform, GlobalTransform>)`.
+///
+/// Commands are deferred, so `T` is only visible to systems running after the inserts are
+/// applied. Inserting twice, e.g. by two instances running in the same stage, overwrites the
+/// first insert with an identical value.
+pub fn insert_missing<Source, T>(mut cmd: Commands, q: Query<(EntityId, &Source), WithOut<T>>)
+where
+    Source: Component,
+    T: Component + for<'a> From<&'a Source>,
+{
+    for (id, source) in q.iter() {
+        cmd.entity(id).insert(T::from(source));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Radius(f32);
+    struct Area(f32);
+
+    impl From<&Radius> for Area {
+        fn from(r: &Radius) -> Self {
+            Area(r.0 * r.0 * std::f32::consts::PI)
+        }
+    }
+
+    #[test]
+    fn insert_missing_test() {
+        let mut world = World::new(4);
+        world
+            .run_system(|mut cmd: Commands| {
+                cmd.spawn().insert(Radius(1.0));
+                cmd.spawn().insert(Radius(2.0)).insert(Area(-1.0));
+            })
+            .unwrap();
+        world.run_system(insert_missing::<Radius, Area>).unwrap();
+
+        world.run_view_system(|q: Query<(&Radius, &Area)>| {
+            let mut areas = q.iter().map(|(r, a)| (r.0, a.0)).collect::<Vec<_>>();
+            areas.sort_by(|a, b| a.0.total_cmp(&b.0));
+            // existing components are left alone
+            assert_eq!(areas, [(1.0, std::f32::consts::PI), (2.0, -1.0)]);
+        });
+    }
+}
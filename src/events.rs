@@ -0,0 +1,94 @@
+use std::marker::PhantomData;
+
+use cecs::prelude::*;
+
+use crate::{App, Plugin, Stage};
+
+/// Game world resource buffering events of type `T`.
+///
+/// Events sent during a tick can be read in the next tick, after which they're dropped.
+/// Register the resource via [EventsPlugin].
+pub struct Events<T> {
+    events: Vec<T>,
+    next: Vec<T>,
+}
+
+impl<T> Default for Events<T> {
+    fn default() -> Self {
+        Self {
+            events: Vec::new(),
+            next: Vec::new(),
+        }
+    }
+}
+
+impl<T> Events<T> {
+    pub fn send(&mut self, event: T) {
+        self.next.push(event);
+    }
+
+    /// Events sent in the previous tick
+    pub fn iter(&self) -> impl Iterator<Item = &T> {
+        self.events.iter()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.events.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.events.len()
+    }
+
+    /// Drop the events of the previous tick and publish the events sent since
+    pub fn update(&mut self) {
+        self.events.clear();
+        std::mem::swap(&mut self.events, &mut self.next);
+    }
+}
+
+fn update_events<T: Component>(mut events: ResMut<Events<T>>) {
+    events.update();
+}
+
+pub struct EventsPlugin<T> {
+    _m: PhantomData<T>,
+}
+
+impl<T> Default for EventsPlugin<T> {
+    fn default() -> Self {
+        Self { _m: PhantomData }
+    }
+}
+
+impl<T: Component> Plugin for EventsPlugin<T> {
+    fn build(self, app: &mut App) {
+        app.insert_resource(Events::<T>::default());
+        app.with_stage(Stage::PreUpdate, |s| {
+            s.add_system(update_events::<T>);
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_events_are_delivered_once() {
+        let mut events = Events::default();
+        events.send(1);
+        events.send(2);
+        assert!(events.is_empty());
+
+        events.update();
+        assert_eq!(events.iter().copied().collect::<Vec<_>>(), [1, 2]);
+
+        events.send(3);
+        events.update();
+        assert_eq!(events.iter().copied().collect::<Vec<_>>(), [3]);
+
+        events.update();
+        assert!(events.is_empty());
+    }
+}
@@ -1,5 +1,7 @@
 pub mod assets;
 pub mod camera;
+pub mod commands_ext;
+pub mod events;
 pub mod prelude;
 pub mod quat_ext;
 pub mod renderer;
@@ -9,6 +11,7 @@ pub mod transform;
 pub mod audio;
 
 use anyhow::Context;
+use glam::{UVec2, Vec2};
 // reexport
 pub use cecs;
 pub use glam;
@@ -20,14 +23,14 @@ pub use winit;
 use winit::{
     application::ApplicationHandler,
     event::*,
-    keyboard::{KeyCode, PhysicalKey},
+    keyboard::{KeyCode, ModifiersState, PhysicalKey},
     window::{Theme, WindowAttributes},
 };
 
 use parking_lot::Mutex;
 use std::{
     any::TypeId,
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     ptr::NonNull,
     sync::{atomic::AtomicBool, Arc},
     thread::JoinHandle,
@@ -35,9 +38,12 @@ use std::{
 };
 use transform::TransformPlugin;
 
-use renderer::{GraphicsState, RenderResult, RendererPlugin, WindowSize};
+use renderer::{
+    GraphicsState, PreferredSurfaceFormat, RedrawMode, RedrawRequest, RenderResult, RenderStats,
+    RendererPlugin, SurfaceErrorAction, SurfacePolicy, WindowSize,
+};
 
-use winit::event_loop::EventLoop;
+use winit::event_loop::{ControlFlow, EventLoop};
 
 use cecs::prelude::*;
 
@@ -59,9 +65,81 @@ pub struct GameWorld {
     world: NonNull<World>,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Render world resource counting successful extractions.
+///
+/// Incremented before the extract stage runs, so systems added via [App::add_extract_system]
+/// can take `Res<ExtractionTick>` to version the data they extract. Extracted entities are
+/// tagged with the tick they were last updated in, and deleted once stale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct ExtractionTick(pub u32);
 
+/// Remembers the extraction that last updated some render-side data
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct LastExtracted(pub Option<ExtractionTick>);
+
+impl LastExtracted {
+    pub fn mark(&mut self, tick: ExtractionTick) {
+        self.0 = Some(tick);
+    }
+
+    /// Returns true if the data was not updated by the extraction of `tick`
+    pub fn is_stale(&self, tick: ExtractionTick) -> bool {
+        self.0 != Some(tick)
+    }
+}
+
+/// Controls how engine systems use the thread pool.
+///
+/// Insert into the game world, it's extracted into the render world automatically.
+/// Note that `par_for_each` calls of user systems are not affected.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ParallelConfig {
+    /// Run the engine's parallel workloads (transform propagation, sprite instance gathering)
+    /// sequentially, in a stable order. Useful when a data race or ordering bug is suspected.
+    pub single_threaded: bool,
+}
+
+/// Render world resource configuring the extraction of the game world
+#[derive(Debug, Clone, Copy)]
+pub struct ExtractionConfig {
+    /// How long the render thread waits for the game world's lock before giving up and
+    /// rendering the previous frame's data again.
+    ///
+    /// Longer timeouts drop fewer frames when the simulation is heavy, but every
+    /// millisecond spent waiting is added to the input-to-photon latency. Latency sensitive
+    /// applications should prefer shorter timeouts.
+    pub game_lock_timeout: Duration,
+}
+
+impl Default for ExtractionConfig {
+    fn default() -> Self {
+        Self {
+            game_lock_timeout: Duration::from_millis(1),
+        }
+    }
+}
+
+/// Game world resource controlling the game loop. Useful for debugging the simulation.
+///
+/// While `paused` the game world is not ticked, only the systems registered via
+/// [App::add_paused_system] are ran. Rendering is not affected.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct GameLoopControl {
+    pub paused: bool,
+    /// Run exactly one tick, then pause again
+    pub step_once: bool,
+}
+
+impl GameLoopControl {
+    pub fn toggle_pause(&mut self) {
+        self.paused = !self.paused;
+    }
+
+    pub fn step(&mut self) {
+        self.step_once = true;
+    }
+}
+
 unsafe impl Send for GameWorld {}
 unsafe impl Sync for GameWorld {}
 
@@ -69,6 +147,12 @@ impl GameWorld {
     pub fn world(&self) -> &World {
         unsafe { self.world.as_ref() }
     }
+
+    /// Take `ResMut<GameWorld>` to move data out of the game world instead of cloning it.
+    /// Extract systems taking it run exclusively.
+    pub fn world_mut(&mut self) -> &mut World {
+        unsafe { self.world.as_mut() }
+    }
 }
 
 impl Timer {
@@ -109,7 +193,8 @@ impl Timer {
     }
 }
 
-fn update_time(mut time: ResMut<Time>, mut dt: ResMut<DeltaTime>) {
+/// Updates [Time] and [DeltaTime], in [Stage::PreUpdate]. Also runs while the game is paused.
+pub fn update_time(mut time: ResMut<Time>, mut dt: ResMut<DeltaTime>) {
     let now = instant::Instant::now();
     dt.0 = now - time.0;
     time.0 = now;
@@ -121,6 +206,17 @@ fn extract_render_data(
     render_world: &mut World,
     render_extract: &SystemStage,
 ) {
+    let timeout = render_world
+        .get_resource::<ExtractionConfig>()
+        .copied()
+        .unwrap_or_default()
+        .game_lock_timeout;
+    let Some(mut gw) = game_world.try_lock_for(timeout) else {
+        // the previous frame's data is rendered again
+        // do not advance the tick, or the GC would delete the previously extracted entities
+        tracing::trace!("Failed to lock the game world, skipping extraction");
+        return;
+    };
     render_world
         .run_system(
             |mut cmd: Commands, tick: Option<ResMut<ExtractionTick>>| match tick {
@@ -129,16 +225,25 @@ fn extract_render_data(
             },
         )
         .unwrap();
-    let Some(mut gw) = game_world.try_lock_for(Duration::from_millis(1)) else {
-        return;
-    };
     render_world.insert_resource(GameWorld {
         world: NonNull::from(&mut *gw),
     });
     render_world.run_stage(render_extract.clone()).unwrap();
     render_world.remove_resource::<GameWorld>();
+    if let Some(stats) = render_world.get_resource::<RenderStats>() {
+        gw.insert_resource(*stats);
+    }
+    #[cfg(feature = "profile")]
+    if let Some(timings) = render_world.get_resource::<StageTimings>() {
+        gw.insert_resource(RenderStageTimings(timings.clone()));
+    }
 }
 
+/// The game world and its systems, with the render world in `render_app`.
+///
+/// `App` derefs to the game [World], so `World::run_system` and `World::run_view_system` can be
+/// used to run one-off systems before [App::run], e.g. in tests. See [App::update_once] for
+/// ticking the game world without a window.
 pub struct App {
     world: World,
     stages: std::collections::BTreeMap<Stage, SystemStage<'static>>,
@@ -146,6 +251,8 @@ pub struct App {
     plugins: HashSet<TypeId>,
 
     extact_stage: SystemStage<'static>,
+    paused_stage: SystemStage<'static>,
+    shutdown_stage: SystemStage<'static>,
     pub render_app: Option<Box<App>>,
 }
 
@@ -155,6 +262,117 @@ pub trait Plugin {
     fn build(self, app: &mut App);
 }
 
+/// An ordered list of plugins that can be customized before adding them to an [App]
+pub trait PluginGroup {
+    fn build(self) -> PluginGroupBuilder;
+}
+
+struct PluginEntry {
+    id: TypeId,
+    name: &'static str,
+    enabled: bool,
+    build: Box<dyn FnOnce(&mut App)>,
+}
+
+impl PluginEntry {
+    fn new<T: Plugin + 'static>(plugin: T) -> Self {
+        Self {
+            id: TypeId::of::<T>(),
+            name: std::any::type_name::<T>(),
+            enabled: true,
+            build: Box::new(move |app: &mut App| {
+                app.add_plugin(plugin);
+            }),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct PluginGroupBuilder {
+    plugins: Vec<PluginEntry>,
+}
+
+impl PluginGroup for PluginGroupBuilder {
+    fn build(self) -> PluginGroupBuilder {
+        self
+    }
+}
+
+impl PluginGroupBuilder {
+    fn index_of<T: 'static>(&self) -> Option<usize> {
+        let id = TypeId::of::<T>();
+        self.plugins.iter().position(|p| p.id == id)
+    }
+
+    fn expect_index_of<T: 'static>(&self) -> usize {
+        self.index_of::<T>()
+            .unwrap_or_else(|| panic!("Plugin {} is not in the group", std::any::type_name::<T>()))
+    }
+
+    fn insert<T: Plugin + 'static>(mut self, mut index: usize, plugin: T) -> Self {
+        if let Some(i) = self.index_of::<T>() {
+            self.plugins.remove(i);
+            if i < index {
+                index -= 1;
+            }
+        }
+        self.plugins.insert(index, PluginEntry::new(plugin));
+        self
+    }
+
+    /// Add `plugin` to the end of the group. If the group already contains a plugin of the same
+    /// type it is replaced
+    pub fn add<T: Plugin + 'static>(self, plugin: T) -> Self {
+        let index = self.plugins.len();
+        self.insert(index, plugin)
+    }
+
+    /// Add `plugin` before `Target`, panics if `Target` is not in the group
+    pub fn add_before<Target: Plugin + 'static, T: Plugin + 'static>(self, plugin: T) -> Self {
+        let index = self.expect_index_of::<Target>();
+        self.insert(index, plugin)
+    }
+
+    /// Add `plugin` after `Target`, panics if `Target` is not in the group
+    pub fn add_after<Target: Plugin + 'static, T: Plugin + 'static>(self, plugin: T) -> Self {
+        let index = self.expect_index_of::<Target>() + 1;
+        self.insert(index, plugin)
+    }
+
+    /// Replace the plugin of type `T` keeping its position, panics if `T` is not in the group
+    pub fn set<T: Plugin + 'static>(self, plugin: T) -> Self {
+        let index = self.expect_index_of::<T>();
+        self.insert(index, plugin)
+    }
+
+    /// Skip `T` when the group is added, panics if `T` is not in the group
+    pub fn disable<T: Plugin + 'static>(mut self) -> Self {
+        let index = self.expect_index_of::<T>();
+        self.plugins[index].enabled = false;
+        self
+    }
+
+    pub fn enable<T: Plugin + 'static>(mut self) -> Self {
+        let index = self.expect_index_of::<T>();
+        self.plugins[index].enabled = true;
+        self
+    }
+
+    pub fn contains<T: Plugin + 'static>(&self) -> bool {
+        self.index_of::<T>().is_some()
+    }
+
+    fn finish(self, app: &mut App) {
+        for plugin in self.plugins {
+            if plugin.enabled {
+                (plugin.build)(app);
+            } else {
+                tracing::debug!(plugin = plugin.name, "Plugin is disabled");
+            }
+        }
+    }
+}
+
 impl std::ops::Deref for App {
     type Target = World;
 
@@ -172,7 +390,9 @@ impl std::ops::DerefMut for App {
 impl Default for App {
     fn default() -> Self {
         let mut app = App::empty();
-        app.render_app = Some(Box::new(App::empty()));
+        let mut render_app = App::empty();
+        render_app.insert_resource(ExtractionConfig::default());
+        app.render_app = Some(Box::new(render_app));
         app
     }
 }
@@ -181,14 +401,34 @@ enum RunningApp {
     Pending(App),
     Initialized {
         render_world: World,
+        render_schedule: Schedule,
         render_extract: SystemStage<'static>,
         game_world: Arc<Mutex<World>>,
         game_thread: JoinHandle<()>,
         enabled: Arc<AtomicBool>,
+        visibility: WindowVisibility,
+        shutdown_stage: SystemStage<'static>,
+        /// Buttons held according to the window events, raw motion is only forwarded to the
+        /// game world while any is held, see [MouseInputs::capture_drags]
+        pressed_buttons: HashSet<MouseButton>,
+        capture: Arc<AtomicBool>,
     },
     Terminated,
 }
 
+/// Rendering is skipped while the window can not be seen
+#[derive(Debug, Default, Clone, Copy)]
+struct WindowVisibility {
+    occluded: bool,
+    minimized: bool,
+}
+
+impl WindowVisibility {
+    fn is_visible(&self) -> bool {
+        !self.occluded && !self.minimized
+    }
+}
+
 impl RunningApp {
     fn as_pending(&mut self) -> Option<&mut App> {
         if let Self::Pending(v) = self {
@@ -210,16 +450,30 @@ impl RunningApp {
         if let RunningApp::Initialized {
             game_thread,
             enabled,
+            game_world,
+            shutdown_stage,
             ..
         } = std::mem::replace(self, RunningApp::Terminated)
         {
             enabled.store(false, std::sync::atomic::Ordering::Relaxed);
             game_thread.join().expect("Failed to join game thread");
+            // the game thread has stopped, the shutdown systems have exclusive access
+            if !shutdown_stage.is_empty() {
+                tracing::debug!("Running shutdown systems");
+                if let Err(err) = game_world.lock().run_stage(shutdown_stage) {
+                    tracing::error!(?err, "Shutdown systems failed");
+                }
+            }
         }
     }
 }
 
-fn game_thread(game_world: Arc<Mutex<World>>, enabled: Arc<AtomicBool>) {
+fn game_thread(
+    game_world: Arc<Mutex<World>>,
+    enabled: Arc<AtomicBool>,
+    schedule: Schedule,
+    paused_stage: SystemStage<'static>,
+) {
     // TODO: take from resource
     let target_frame_latency: Duration = Duration::from_millis(15);
     // reset Time so the first DT isn't outragous
@@ -230,7 +484,21 @@ fn game_thread(game_world: Arc<Mutex<World>>, enabled: Arc<AtomicBool>) {
         let start = Instant::now();
 
         let mut game_world = game_world.lock();
-        game_world.tick();
+        let control = game_world
+            .get_resource::<GameLoopControl>()
+            .copied()
+            .unwrap_or_default();
+        if control.paused && !control.step_once {
+            game_world.run_stage(paused_stage.clone()).unwrap();
+        } else {
+            schedule.tick(&mut game_world);
+            if control.step_once {
+                if let Some(control) = game_world.get_resource_mut::<GameLoopControl>() {
+                    control.step_once = false;
+                    control.paused = true;
+                }
+            }
+        }
         drop(game_world);
 
         let end = Instant::now();
@@ -245,6 +513,15 @@ fn game_thread(game_world: Arc<Mutex<World>>, enabled: Arc<AtomicBool>) {
     }
 }
 
+/// How often the event loop checks for redraw requests in [RedrawMode::OnDemand]
+const ON_DEMAND_POLL_INTERVAL: Duration = Duration::from_millis(15);
+
+fn push_mouse_input(game_world: &Mutex<World>, input: MouseInput) {
+    if let Some(mouse) = game_world.lock().get_resource_mut::<MouseInputs>() {
+        mouse.next.push(input);
+    }
+}
+
 impl ApplicationHandler for RunningApp {
     fn resumed(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
         let Some(app) = self.as_pending() else {
@@ -255,30 +532,47 @@ impl ApplicationHandler for RunningApp {
             .create_window(attributes.clone())
             .expect("Failed to create window");
         let window = Arc::new(window);
+        // the first resize event may only arrive after the first frame
+        app.insert_resource(WindowSize::from(window.inner_size()));
         // FIXME:
         // do not block here
-        let graphics_state = pollster::block_on(GraphicsState::new(Arc::clone(&window)));
+        let preferred_format = app
+            .render_app_mut()
+            .world
+            .get_resource::<PreferredSurfaceFormat>()
+            .copied();
+        let graphics_state =
+            pollster::block_on(GraphicsState::new(Arc::clone(&window), preferred_format));
 
         app.render_app_mut().insert_resource(graphics_state);
 
         let InitializedWorlds {
             game_world,
+            game_schedule,
             render_world,
+            render_schedule,
             render_extract,
+            paused_stage,
+            shutdown_stage,
         } = std::mem::take(app).build();
         let game_world = Arc::new(Mutex::new(game_world));
         let enabled = Arc::new(AtomicBool::new(true));
         let game_thread = std::thread::spawn({
             let game_world = Arc::clone(&game_world);
             let enabled = Arc::clone(&enabled);
-            move || game_thread(game_world, enabled)
+            move || game_thread(game_world, enabled, game_schedule, paused_stage)
         });
         *self = RunningApp::Initialized {
             render_world,
+            render_schedule,
             game_world,
             game_thread,
             render_extract,
             enabled,
+            visibility: WindowVisibility::default(),
+            shutdown_stage,
+            pressed_buttons: Default::default(),
+            capture: Arc::new(AtomicBool::new(false)),
         };
     }
 
@@ -291,13 +585,23 @@ impl ApplicationHandler for RunningApp {
         tracing::trace!(?event, "Event received");
         let RunningApp::Initialized {
             render_world,
+            render_schedule,
             game_world,
             render_extract,
+            visibility,
+            pressed_buttons,
+            capture,
             ..
         } = self
         else {
             return;
         };
+        if !matches!(event, WindowEvent::RedrawRequested) {
+            // input and window changes may change what's on screen
+            if let Some(redraw) = render_world.get_resource::<RedrawRequest>() {
+                redraw.request();
+            }
+        }
         match event {
             #[cfg(not(target_family = "wasm"))]
             WindowEvent::CloseRequested
@@ -313,15 +617,23 @@ impl ApplicationHandler for RunningApp {
                 self.stop();
                 event_loop.exit();
             }
+            WindowEvent::Occluded(occluded) => {
+                if visibility.occluded != occluded {
+                    tracing::debug!(occluded, "Window occlusion changed");
+                }
+                visibility.occluded = occluded;
+            }
             WindowEvent::Resized(size) => {
+                let minimized = size.width == 0 || size.height == 0;
+                if visibility.minimized != minimized {
+                    tracing::debug!(minimized, "Window minimized state changed");
+                }
+                visibility.minimized = minimized;
                 let w = Arc::clone(game_world);
                 render_world
                     .run_system(move |mut state: ResMut<GraphicsState>| {
                         let mut w = w.lock();
-                        w.insert_resource(WindowSize {
-                            width: size.width,
-                            height: size.height,
-                        });
+                        w.insert_resource(WindowSize::from(size));
 
                         state.resize(size);
                     })
@@ -337,41 +649,146 @@ impl ApplicationHandler for RunningApp {
                     .next
                     .push(event.clone());
             }
+            WindowEvent::Focused(false) => {
+                pressed_buttons.clear();
+                capture.store(false, std::sync::atomic::Ordering::Relaxed);
+                // key and button releases are not delivered to unfocused windows
+                let mut game_world = game_world.lock();
+                if let Some(keyboard) = game_world.get_resource_mut::<KeyBoardInputs>() {
+                    keyboard.release_all = true;
+                }
+                if let Some(mouse) = game_world.get_resource_mut::<MouseInputs>() {
+                    mouse.next.push(MouseInput::FocusLost);
+                }
+            }
+            WindowEvent::CursorMoved { position, .. } => {
+                push_mouse_input(
+                    game_world,
+                    MouseInput::CursorMoved(Vec2::new(position.x as f32, position.y as f32)),
+                );
+            }
+            WindowEvent::MouseInput { state, button, .. } => {
+                match state {
+                    ElementState::Pressed => pressed_buttons.insert(button),
+                    ElementState::Released => pressed_buttons.remove(&button),
+                };
+                capture.store(
+                    !pressed_buttons.is_empty(),
+                    std::sync::atomic::Ordering::Relaxed,
+                );
+                push_mouse_input(game_world, MouseInput::Button { button, state });
+            }
+            WindowEvent::MouseWheel { delta, .. } => {
+                push_mouse_input(game_world, MouseInput::Scroll(delta));
+            }
             WindowEvent::RedrawRequested => {
+                if !visibility.is_visible() {
+                    return;
+                }
                 extract_render_data(&game_world, render_world, render_extract);
 
-                render_world.tick();
-
-                let result = render_world.get_resource::<RenderResult>();
-                if let Some(result) = result {
-                    match result {
-                        Ok(_) => {}
-                        // handled by the renderer system
-                        Err(wgpu::SurfaceError::Lost) => {
-                            tracing::info!("Surface lost")
+                render_schedule.tick(render_world);
+
+                let policy = render_world
+                    .get_resource::<SurfacePolicy>()
+                    .copied()
+                    .unwrap_or_default();
+                let result = render_world.get_resource::<RenderResult>().cloned();
+                let action = match result {
+                    None | Some(Ok(_)) => None,
+                    // handled by the renderer system, until it runs out of attempts
+                    Some(Err(wgpu::SurfaceError::Lost)) => {
+                        let lost_count = render_world
+                            .get_resource::<GraphicsState>()
+                            .map(|s| s.lost_count())
+                            .unwrap_or(0);
+                        if lost_count > policy.max_reconfigures {
+                            tracing::error!(lost_count, "Surface lost, giving up reconfiguring");
+                            Some(policy.on_surface_lost_exhausted)
+                        } else {
+                            tracing::info!("Surface lost");
+                            None
                         }
-                        // The system is out of memory, we should probably quit
-                        Err(wgpu::SurfaceError::OutOfMemory) => {
-                            tracing::error!("gpu out of memory");
-                            self.stop();
-                            event_loop.exit();
-                        }
-                        // All other errors (Outdated, Timeout) should be resolved by the next frame
-                        Err(e) => tracing::info!("rendering failed: {:?}", e),
                     }
+                    Some(Err(wgpu::SurfaceError::OutOfMemory)) => {
+                        tracing::error!("gpu out of memory");
+                        Some(policy.on_out_of_memory)
+                    }
+                    // All other errors (Outdated, Timeout) should be resolved by the next frame
+                    Some(Err(e)) => {
+                        tracing::info!("rendering failed: {:?}", e);
+                        None
+                    }
+                };
+                if action == Some(SurfaceErrorAction::Exit) {
+                    self.stop();
+                    event_loop.exit();
                 }
             }
             _ => {}
         }
     }
 
-    fn about_to_wait(&mut self, _event_loop: &winit::event_loop::ActiveEventLoop) {
-        tracing::trace!("• about_to_wait");
-        if let RunningApp::Terminated = self {
-            tracing::trace!("x about_to_wait");
+    fn device_event(
+        &mut self,
+        _event_loop: &winit::event_loop::ActiveEventLoop,
+        _device_id: DeviceId,
+        event: DeviceEvent,
+    ) {
+        let RunningApp::Initialized {
+            game_world,
+            capture,
+            ..
+        } = self
+        else {
             return;
+        };
+        // raw motion arrives at a high rate, only lock the game world while it's used
+        if !capture.load(std::sync::atomic::Ordering::Relaxed) {
+            return;
+        }
+        if let DeviceEvent::MouseMotion { delta: (x, y) } = event {
+            push_mouse_input(
+                game_world,
+                MouseInput::Motion(Vec2::new(x as f32, y as f32)),
+            );
+        }
+    }
+
+    fn about_to_wait(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) {
+        tracing::trace!("• about_to_wait");
+        match self {
+            RunningApp::Terminated => {
+                tracing::trace!("x about_to_wait");
+                return;
+            }
+            RunningApp::Initialized { visibility, .. } if !visibility.is_visible() => {
+                // do not spin while hidden, rendering resumes on the next visibility event
+                tracing::trace!("x about_to_wait: window is not visible");
+                return;
+            }
+            _ => {}
+        }
+        let world = self.world_mut();
+        let mode = world
+            .get_resource::<RedrawMode>()
+            .copied()
+            .unwrap_or_default();
+        if mode == RedrawMode::OnDemand {
+            // before the first extraction there is no request to read, render the first frame
+            let requested = world
+                .get_resource::<RedrawRequest>()
+                .map(|r| r.take())
+                .unwrap_or(true);
+            if !requested {
+                // systems request redraws from the game thread, wake up to check on them
+                event_loop.set_control_flow(ControlFlow::wait_duration(ON_DEMAND_POLL_INTERVAL));
+                tracing::trace!("x about_to_wait: no redraw requested");
+                return;
+            }
         }
-        self.world_mut()
+        event_loop.set_control_flow(ControlFlow::Wait);
+        world
             .run_system(|gs: Res<GraphicsState>| {
                 let window = gs.window();
                 tracing::trace!("redraw {window:?}");
@@ -394,11 +811,14 @@ impl App {
     fn empty() -> Self {
         let mut world = World::new(1024);
         world.insert_resource(WindowDescriptor::default());
+        world.insert_resource(GameLoopControl::default());
         Self {
             world,
             stages: Default::default(),
             startup_systems: SystemStage::new("startup"),
             extact_stage: SystemStage::new("extract"),
+            paused_stage: SystemStage::new("paused"),
+            shutdown_stage: SystemStage::new("shutdown"),
             plugins: Default::default(),
             render_app: None,
         }
@@ -415,6 +835,16 @@ impl App {
         self
     }
 
+    /// Add every enabled plugin of the group, in order
+    pub fn add_plugins(&mut self, group: impl PluginGroup) -> &mut Self {
+        group.build().finish(self);
+        self
+    }
+
+    pub fn has_plugin<T: Plugin + 'static>(&self) -> bool {
+        self.plugins.contains(&TypeId::of::<T>())
+    }
+
     pub fn with_stage(
         &mut self,
         stage: Stage,
@@ -440,6 +870,26 @@ impl App {
         self
     }
 
+    /// Paused systems are ran instead of the regular stages while the game loop is paused.
+    /// See [GameLoopControl]
+    pub fn add_paused_system<P>(
+        &mut self,
+        sys: impl cecs::systems::IntoSystem<'static, P, ()>,
+    ) -> &mut Self {
+        self.paused_stage.add_system(sys);
+        self
+    }
+
+    /// Shutdown systems are ran once on the game world when the app exits, after the game loop
+    /// has stopped. Use them to e.g. persist state on quit.
+    pub fn add_shutdown_system<P>(
+        &mut self,
+        sys: impl cecs::systems::IntoSystem<'static, P, ()>,
+    ) -> &mut Self {
+        self.shutdown_stage.add_system(sys);
+        self
+    }
+
     pub fn add_extract_system<P>(
         &mut self,
         sys: impl cecs::systems::IntoSystem<'static, P, ()>,
@@ -448,14 +898,53 @@ impl App {
         self
     }
 
+    /// Tick the game world once, without a window or event loop. Useful for testing gameplay
+    /// logic headless.
+    ///
+    /// The startup systems are ran on the first call. The render world is not ticked, and
+    /// [GameLoopControl] is ignored.
+    pub fn update_once(&mut self) -> anyhow::Result<()> {
+        self.run_startup_systems()?;
+        for (id, stage) in self.stages.iter().filter(|(_, s)| !s.is_empty()) {
+            self.world
+                .run_stage(stage.clone())
+                .map_err(|err| anyhow::anyhow!("Failed to run stage {id:?}: {err:?}"))?;
+        }
+        Ok(())
+    }
+
+    /// Startup systems are ran once, subsequent calls are noops
+    fn run_startup_systems(&mut self) -> anyhow::Result<()> {
+        if self.startup_systems.is_empty() {
+            return Ok(());
+        }
+        let startup = std::mem::replace(&mut self.startup_systems, SystemStage::new("startup"));
+        self.world
+            .run_stage(startup)
+            .map_err(|err| anyhow::anyhow!("Failed to run startup systems: {err:?}"))?;
+        Ok(())
+    }
+
     pub async fn run(mut self) -> anyhow::Result<()> {
         let event_loop = EventLoop::new().context("Failed to initialize EventLoop")?;
 
         let window = self.world.run_view_system(|desc: Res<WindowDescriptor>| {
-            WindowAttributes::default()
+            let physical = |size: UVec2| winit::dpi::PhysicalSize::new(size.x, size.y);
+            let mut attributes = WindowAttributes::default()
                 .with_title(&desc.title)
                 .with_fullscreen(desc.fullscreen.clone())
-                .with_theme(Some(Theme::Dark))
+                .with_resizable(desc.resizable)
+                .with_theme(Some(Theme::Dark));
+            if let Some(size) = desc.size {
+                attributes = attributes.with_inner_size(physical(size));
+            }
+            if let Some(size) = desc.min_size {
+                attributes = attributes.with_min_inner_size(physical(size));
+            }
+            if let Some(size) = desc.max_size {
+                attributes = attributes.with_max_inner_size(physical(size));
+            }
+            attributes
         });
 
         self.world.insert_resource(window);
@@ -466,18 +955,22 @@ impl App {
         Ok(())
     }
 
-    fn _build(self) -> World {
+    fn _build(self) -> (World, Schedule) {
         let mut world = self.world;
-        for (_, stage) in self
+        let mut schedule = Schedule::default();
+        for (_stage_id, stage) in self
             .stages
             .into_iter()
             .filter(|(_, stage)| !stage.is_empty())
         {
+            #[cfg(not(feature = "profile"))]
             world.add_stage(stage);
+            #[cfg(feature = "profile")]
+            schedule.stages.push((_stage_id, stage));
         }
         world.run_stage(self.startup_systems).unwrap();
         world.vacuum();
-        world
+        (world, schedule)
     }
 
     fn build(mut self) -> InitializedWorlds {
@@ -490,32 +983,91 @@ impl App {
         {
             tracing::warn!("Rendering is performed in a sub-app not the main app. But the main app's Render stage is non-empty.");
         }
-        let rw = self
+        let (rw, render_schedule) = self
             .render_app
             .take()
             .map(|a| a._build())
-            .unwrap_or_else(|| World::new(4));
+            .unwrap_or_else(|| (World::new(4), Schedule::default()));
         let render_extract = std::mem::replace(&mut self.extact_stage, SystemStage::new("nil"));
-        let w = self._build();
+        let paused_stage = std::mem::replace(&mut self.paused_stage, SystemStage::new("nil"));
+        let shutdown_stage = std::mem::replace(&mut self.shutdown_stage, SystemStage::new("nil"));
+        let (w, game_schedule) = self._build();
         InitializedWorlds {
             game_world: w,
+            game_schedule,
             render_world: rw,
+            render_schedule,
             render_extract,
+            paused_stage,
+            shutdown_stage,
+        }
+    }
+}
+
+/// Time it took to run each stage in the last tick
+///
+/// Both the game and the render world hold their own timings. The render world's timings are
+/// copied into the game world as [RenderStageTimings] during extraction.
+#[cfg(feature = "profile")]
+#[derive(Debug, Default, Clone)]
+pub struct StageTimings(pub std::collections::BTreeMap<Stage, Duration>);
+
+#[cfg(feature = "profile")]
+#[derive(Debug, Default, Clone)]
+pub struct RenderStageTimings(pub StageTimings);
+
+/// Runs the stages of a world
+#[derive(Default)]
+struct Schedule {
+    /// When profiling, stages are ran one-by-one instead of by `World::tick`, so they can be
+    /// measured
+    #[cfg(feature = "profile")]
+    stages: Vec<(Stage, SystemStage<'static>)>,
+}
+
+impl Schedule {
+    fn tick(&self, world: &mut World) {
+        #[cfg(not(feature = "profile"))]
+        world.tick();
+        #[cfg(feature = "profile")]
+        {
+            let mut timings = StageTimings::default();
+            for (stage, systems) in self.stages.iter() {
+                let start = Instant::now();
+                world.run_stage(systems.clone()).unwrap();
+                timings.0.insert(*stage, start.elapsed());
+            }
+            world.insert_resource(timings);
         }
     }
 }
 
 struct InitializedWorlds {
     pub game_world: World,
+    pub game_schedule: Schedule,
     pub render_world: World,
+    pub render_schedule: Schedule,
     pub render_extract: SystemStage<'static>,
+    pub paused_stage: SystemStage<'static>,
+    pub shutdown_stage: SystemStage<'static>,
 }
 
+/// Stages of a tick, ran in order.
+///
+/// Built-in systems are public, so systems in the same stage can be ordered relative to them
+/// via `.after(..)`, e.g. `my_input_remap.after(brengin::update_inputs)`. Ordering across
+/// stages is implied by the stage order.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub enum Stage {
+    /// Time, inputs and events are updated, see [update_time] and [update_inputs]
     PreUpdate = 1,
+    /// Game logic. Cameras compute their view-projection here, see
+    /// [camera::update_view_projections]
     Update = 2,
+    /// Hierarchy bookkeeping, e.g. appending new children
     PostUpdate = 3,
+    /// [transform::GlobalTransform]s are computed, see [transform::update_root_transforms] and
+    /// [transform::update_child_transforms]
     Transform = 4,
     Render = 5,
 }
@@ -527,6 +1079,12 @@ pub struct KeyBoardInputs {
     pub pressed: HashSet<KeyCode>,
     pub just_released: HashSet<KeyCode>,
     pub just_pressed: HashSet<KeyCode>,
+    /// Modifier keys currently held down. Left and right variants are treated the same.
+    pub modifiers: ModifiersState,
+    /// Text produced by the last press of a key
+    text: HashMap<KeyCode, String>,
+    /// Release every pressed key on the next update
+    pub(crate) release_all: bool,
 }
 
 impl KeyBoardInputs {
@@ -538,11 +1096,14 @@ impl KeyBoardInputs {
         for k in self.inputs.iter() {
             match k.state {
                 ElementState::Pressed => {
-                    if let PhysicalKey::Code(k) = k.physical_key {
-                        if !self.pressed.contains(&k) {
-                            self.just_pressed.insert(k);
+                    if let PhysicalKey::Code(code) = k.physical_key {
+                        if !self.pressed.contains(&code) {
+                            self.just_pressed.insert(code);
+                        }
+                        self.pressed.insert(code);
+                        if let Some(text) = k.text.as_ref() {
+                            self.text.insert(code, text.to_string());
                         }
-                        self.pressed.insert(k);
                     }
                 }
                 ElementState::Released => {
@@ -553,21 +1114,283 @@ impl KeyBoardInputs {
                 }
             }
         }
+        if std::mem::take(&mut self.release_all) {
+            self.release_all();
+        }
+        self.modifiers = modifiers_of(&self.pressed);
+    }
+
+    /// Release every pressed key, as if the user let go of them
+    pub fn release_all(&mut self) {
+        for code in self.pressed.drain() {
+            self.just_pressed.remove(&code);
+            self.just_released.insert(code);
+        }
+        self.modifiers = ModifiersState::empty();
+    }
+
+    /// The text the key produced the last time it was pressed.
+    /// Returns `None` for keys that do not map to printable text, or that haven't been pressed
+    /// yet.
+    pub fn key_text(&self, code: KeyCode) -> Option<&str> {
+        self.text.get(&code).map(|t| t.as_str())
+    }
+
+    pub fn pressed_keys(&self) -> impl Iterator<Item = KeyCode> + '_ {
+        self.pressed.iter().copied()
+    }
+
+    pub fn ctrl(&self) -> bool {
+        self.modifiers.control_key()
+    }
+
+    pub fn shift(&self) -> bool {
+        self.modifiers.shift_key()
+    }
+
+    pub fn alt(&self) -> bool {
+        self.modifiers.alt_key()
+    }
+
+    pub fn super_key(&self) -> bool {
+        self.modifiers.super_key()
+    }
+
+    /// The shortcut's key was pressed this tick, while exactly its modifiers are held
+    pub fn shortcut_pressed(&self, shortcut: &Shortcut) -> bool {
+        self.just_pressed.contains(&shortcut.key) && self.modifiers == shortcut.mods
+    }
+}
+
+/// Key combination, e.g. `Ctrl+S`. Check it via [KeyBoardInputs::shortcut_pressed].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Shortcut {
+    pub mods: ModifiersState,
+    pub key: KeyCode,
+}
+
+impl Shortcut {
+    pub fn new(mods: ModifiersState, key: KeyCode) -> Self {
+        Self { mods, key }
+    }
+
+    pub fn ctrl(key: KeyCode) -> Self {
+        Self::new(ModifiersState::CONTROL, key)
+    }
+}
+
+impl std::fmt::Display for Shortcut {
+    /// Human readable form, e.g. `Ctrl+Shift+S`
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (flag, name) in [
+            (ModifiersState::CONTROL, "Ctrl"),
+            (ModifiersState::SHIFT, "Shift"),
+            (ModifiersState::ALT, "Alt"),
+            (ModifiersState::SUPER, "Super"),
+        ] {
+            if self.mods.contains(flag) {
+                write!(f, "{name}+")?;
+            }
+        }
+        let key = format!("{:?}", self.key);
+        let key = key
+            .strip_prefix("Key")
+            .or_else(|| key.strip_prefix("Digit"))
+            .unwrap_or(&key);
+        f.write_str(key)
+    }
+}
+
+fn modifiers_of(pressed: &HashSet<KeyCode>) -> ModifiersState {
+    let mut modifiers = ModifiersState::empty();
+    for (left, right, flag) in [
+        (
+            KeyCode::ControlLeft,
+            KeyCode::ControlRight,
+            ModifiersState::CONTROL,
+        ),
+        (
+            KeyCode::ShiftLeft,
+            KeyCode::ShiftRight,
+            ModifiersState::SHIFT,
+        ),
+        (KeyCode::AltLeft, KeyCode::AltRight, ModifiersState::ALT),
+        (
+            KeyCode::SuperLeft,
+            KeyCode::SuperRight,
+            ModifiersState::SUPER,
+        ),
+    ] {
+        if pressed.contains(&left) || pressed.contains(&right) {
+            modifiers |= flag;
+        }
+    }
+    modifiers
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum MouseInput {
+    CursorMoved(Vec2),
+    /// Raw, unclamped mouse motion. Also delivered when the cursor is outside the window
+    Motion(Vec2),
+    Button {
+        button: MouseButton,
+        state: ElementState,
+    },
+    Scroll(MouseScrollDelta),
+    /// The window lost focus, release every pressed button
+    FocusLost,
+}
+
+pub struct MouseInputs {
+    /// Cursor position in physical pixels, relative to the top-left corner of the window
+    pub cursor_position: Vec2,
+    /// Distance the cursor moved this tick, in physical pixels
+    pub cursor_delta: Vec2,
+    pub pressed: HashSet<MouseButton>,
+    pub just_released: HashSet<MouseButton>,
+    pub just_pressed: HashSet<MouseButton>,
+    /// Raw scroll events received this tick
+    pub scroll: Vec<MouseScrollDelta>,
+    /// Scroll of this tick in pixels. Line based deltas are converted using `line_height`
+    pub scroll_delta: Vec2,
+    /// Number of pixels a line based scroll delta corresponds to
+    pub line_height: f32,
+    pub(crate) next: Vec<MouseInput>,
+    /// Keep tracking the cursor via raw mouse motion while a button is held, even if the cursor
+    /// leaves the window. Otherwise drags freeze at the window border.
+    ///
+    /// Raw motion is reported in device units, which usually, but not always, match pixels.
+    pub capture_drags: bool,
+    /// Whether `cursor_position` holds a real position yet
+    cursor_known: bool,
+}
+
+impl Default for MouseInputs {
+    fn default() -> Self {
+        Self {
+            cursor_position: Vec2::ZERO,
+            cursor_delta: Vec2::ZERO,
+            pressed: Default::default(),
+            just_released: Default::default(),
+            just_pressed: Default::default(),
+            scroll: Default::default(),
+            scroll_delta: Vec2::ZERO,
+            line_height: 20.0,
+            next: Default::default(),
+            capture_drags: true,
+            cursor_known: false,
+        }
+    }
+}
+
+impl MouseInputs {
+    pub fn update(&mut self) {
+        self.just_released.clear();
+        self.just_pressed.clear();
+        self.scroll.clear();
+        self.scroll_delta = Vec2::ZERO;
+        self.cursor_delta = Vec2::ZERO;
+        let inputs = std::mem::take(&mut self.next);
+        // window events are preferred, raw motion only fills in while the cursor is outside
+        let cursor_moved = inputs
+            .iter()
+            .any(|input| matches!(input, MouseInput::CursorMoved(_)));
+        for input in inputs {
+            match input {
+                MouseInput::CursorMoved(pos) => {
+                    // the first position is not a movement
+                    if self.cursor_known {
+                        self.cursor_delta += pos - self.cursor_position;
+                    }
+                    self.cursor_known = true;
+                    self.cursor_position = pos;
+                }
+                MouseInput::Motion(delta) => {
+                    if !cursor_moved && self.is_captured() && self.cursor_known {
+                        self.cursor_delta += delta;
+                        self.cursor_position += delta;
+                    }
+                }
+                MouseInput::Button {
+                    button,
+                    state: ElementState::Pressed,
+                } => {
+                    if !self.pressed.contains(&button) {
+                        self.just_pressed.insert(button);
+                    }
+                    self.pressed.insert(button);
+                }
+                MouseInput::Button {
+                    button,
+                    state: ElementState::Released,
+                } => {
+                    let was_captured = self.is_captured();
+                    self.pressed.remove(&button);
+                    self.just_released.insert(button);
+                    if was_captured && !self.is_captured() {
+                        self.release_capture();
+                    }
+                }
+                MouseInput::FocusLost => {
+                    let was_captured = self.is_captured();
+                    for button in self.pressed.drain() {
+                        self.just_pressed.remove(&button);
+                        self.just_released.insert(button);
+                    }
+                    if was_captured {
+                        self.release_capture();
+                    }
+                }
+                MouseInput::Scroll(delta) => {
+                    self.scroll_delta += self.normalize_scroll(delta);
+                    self.scroll.push(delta);
+                }
+            }
+        }
+    }
+
+    /// Whether the cursor is tracked via raw motion, see [MouseInputs::capture_drags]
+    pub fn is_captured(&self) -> bool {
+        self.capture_drags && !self.pressed.is_empty()
+    }
+
+    fn release_capture(&mut self) {
+        // the position may have drifted outside the window, resync on the next window event
+        // without reporting the jump as movement
+        self.cursor_known = false;
+    }
+
+    /// Convert a scroll delta to pixels
+    pub fn normalize_scroll(&self, delta: MouseScrollDelta) -> Vec2 {
+        match delta {
+            MouseScrollDelta::LineDelta(x, y) => Vec2::new(x, y) * self.line_height,
+            MouseScrollDelta::PixelDelta(pos) => Vec2::new(pos.x as f32, pos.y as f32),
+        }
     }
 }
 
-fn update_inputs(mut k: ResMut<KeyBoardInputs>) {
+/// Publishes the inputs received since the last tick, in [Stage::PreUpdate]. Also runs while
+/// the game is paused.
+///
+/// Systems in `PreUpdate` that read inputs must run `.after(update_inputs)`.
+pub fn update_inputs(mut k: ResMut<KeyBoardInputs>, mut m: ResMut<MouseInputs>) {
     k.update();
+    m.update();
 }
 
 pub struct InputPlugin;
 impl Plugin for InputPlugin {
     fn build(self, app: &mut App) {
         app.insert_resource(KeyBoardInputs::default());
+        app.insert_resource(MouseInputs::default());
 
         app.with_stage(Stage::PreUpdate, |s| {
             s.add_system(update_time).add_system(update_inputs);
         });
+        // keep inputs and time flowing so the game can be resumed
+        app.add_paused_system(update_time)
+            .add_paused_system(update_inputs);
     }
 }
 
@@ -579,18 +1402,39 @@ impl Plugin for TimePlugin {
     }
 }
 
+/// The engine's default plugins.
+///
+/// Use it as a [PluginGroup] to customize the stack, e.g. to replace the audio plugin:
+/// `app.add_plugins(DefaultPlugins.builder().disable::<audio::AudioPlugin>())`
 pub struct DefaultPlugins;
 
-impl Plugin for DefaultPlugins {
-    fn build(self, app: &mut App) {
-        app.add_plugin(TimePlugin);
-        app.add_plugin(InputPlugin);
+impl DefaultPlugins {
+    pub fn builder(self) -> PluginGroupBuilder {
+        PluginGroup::build(self)
+    }
+}
 
-        app.add_plugin(TransformPlugin);
-        app.add_plugin(RendererPlugin);
+impl PluginGroup for DefaultPlugins {
+    fn build(self) -> PluginGroupBuilder {
+        #[allow(unused_mut)]
+        let mut group = PluginGroupBuilder::default()
+            .add(TimePlugin)
+            .add(InputPlugin)
+            .add(TransformPlugin)
+            .add(RendererPlugin);
 
         #[cfg(feature = "audio")]
-        app.add_plugin(audio::AudioPlugin);
+        {
+            group = group.add(audio::AudioPlugin);
+        }
+
+        group
+    }
+}
+
+impl Plugin for DefaultPlugins {
+    fn build(self, app: &mut App) {
+        app.add_plugins(self);
     }
 }
 
@@ -598,6 +1442,13 @@ impl Plugin for DefaultPlugins {
 pub struct WindowDescriptor {
     pub title: String,
     pub fullscreen: Option<winit::window::Fullscreen>,
+    /// Initial inner size in physical pixels. `None` uses the platform default.
+    pub size: Option<UVec2>,
+    /// Smallest inner size, in physical pixels, the user may resize the window to
+    pub min_size: Option<UVec2>,
+    /// Largest inner size, in physical pixels, the user may resize the window to
+    pub max_size: Option<UVec2>,
+    pub resizable: bool,
 }
 
 // for MacOS:
@@ -610,6 +1461,292 @@ impl Default for WindowDescriptor {
         Self {
             title: "brengin".to_string(),
             fullscreen: None,
+            size: None,
+            min_size: None,
+            max_size: None,
+            resizable: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Default)]
+    struct BuildOrder(Vec<&'static str>);
+
+    macro_rules! order_plugin {
+        ($name: ident) => {
+            struct $name;
+            impl Plugin for $name {
+                fn build(self, app: &mut App) {
+                    app.get_resource_mut::<BuildOrder>()
+                        .unwrap()
+                        .0
+                        .push(stringify!($name));
+                }
+            }
+        };
+    }
+
+    order_plugin!(PluginA);
+    order_plugin!(PluginB);
+    order_plugin!(PluginC);
+    order_plugin!(PluginD);
+
+    #[test]
+    fn test_update_once() {
+        #[derive(Default)]
+        struct Counter {
+            startup: u32,
+            update: u32,
+        }
+
+        let mut app = App::default();
+        app.insert_resource(Counter::default());
+        app.add_startup_system(|mut c: ResMut<Counter>| c.startup += 1);
+        app.with_stage(Stage::Update, |s| {
+            s.add_system(|mut c: ResMut<Counter>| c.update += 1);
+        });
+
+        app.update_once().unwrap();
+        app.update_once().unwrap();
+
+        let counter = app.get_resource::<Counter>().unwrap();
+        assert_eq!(counter.startup, 1);
+        assert_eq!(counter.update, 2);
+    }
+
+    #[test]
+    fn test_plugin_group_order() {
+        let mut app = App::default();
+        app.insert_resource(BuildOrder::default());
+        app.add_plugins(
+            PluginGroupBuilder::default()
+                .add(PluginA)
+                .add(PluginB)
+                .add_after::<PluginA, _>(PluginC)
+                .add_before::<PluginA, _>(PluginD)
+                .disable::<PluginB>(),
+        );
+
+        assert_eq!(
+            app.get_resource::<BuildOrder>().unwrap().0,
+            ["PluginD", "PluginA", "PluginC"]
+        );
+        assert!(app.has_plugin::<PluginA>());
+        assert!(!app.has_plugin::<PluginB>());
+    }
+
+    #[test]
+    fn test_modifiers() {
+        let mut inputs = KeyBoardInputs::default();
+        inputs.pressed.insert(KeyCode::ShiftRight);
+        inputs.pressed.insert(KeyCode::ControlLeft);
+        inputs.pressed.insert(KeyCode::KeyA);
+        inputs.update();
+
+        assert!(inputs.shift());
+        assert!(inputs.ctrl());
+        assert!(!inputs.alt());
+        assert!(!inputs.super_key());
+        assert_eq!(inputs.pressed_keys().count(), 3);
+        assert_eq!(inputs.key_text(KeyCode::KeyA), None);
+    }
+
+    #[test]
+    fn test_shortcut_pressed() {
+        let save = Shortcut::ctrl(KeyCode::KeyS);
+        assert_eq!(save.to_string(), "Ctrl+S");
+
+        let press = |keys: &[KeyCode]| {
+            let mut inputs = KeyBoardInputs::default();
+            inputs.pressed.extend(keys.iter().copied());
+            inputs.modifiers = modifiers_of(&inputs.pressed);
+            inputs.just_pressed.insert(KeyCode::KeyS);
+            inputs.pressed.insert(KeyCode::KeyS);
+            inputs
+        };
+
+        assert!(press(&[KeyCode::ControlLeft]).shortcut_pressed(&save));
+        assert!(press(&[KeyCode::ControlRight]).shortcut_pressed(&save));
+        assert!(!press(&[]).shortcut_pressed(&save));
+        assert!(!press(&[KeyCode::ControlLeft, KeyCode::ShiftLeft]).shortcut_pressed(&save));
+
+        let save_as = Shortcut::new(
+            ModifiersState::CONTROL | ModifiersState::SHIFT,
+            KeyCode::KeyS,
+        );
+        assert_eq!(save_as.to_string(), "Ctrl+Shift+S");
+        assert!(press(&[KeyCode::ControlLeft, KeyCode::ShiftRight]).shortcut_pressed(&save_as));
+
+        // held, but not pressed this tick
+        let mut inputs = press(&[KeyCode::ControlLeft]);
+        inputs.just_pressed.clear();
+        assert!(!inputs.shortcut_pressed(&save));
+    }
+
+    #[test]
+    fn test_cursor_delta() {
+        let mut inputs = MouseInputs::default();
+        inputs
+            .next
+            .push(MouseInput::CursorMoved(Vec2::new(100.0, 100.0)));
+        inputs.update();
+        assert_eq!(inputs.cursor_delta, Vec2::ZERO);
+
+        inputs
+            .next
+            .push(MouseInput::CursorMoved(Vec2::new(110.0, 95.0)));
+        inputs
+            .next
+            .push(MouseInput::CursorMoved(Vec2::new(120.0, 90.0)));
+        inputs.update();
+        assert_eq!(inputs.cursor_delta, Vec2::new(20.0, -10.0));
+
+        inputs.update();
+        assert_eq!(inputs.cursor_delta, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_drag_capture() {
+        let mut inputs = MouseInputs::default();
+        inputs
+            .next
+            .push(MouseInput::CursorMoved(Vec2::new(100.0, 100.0)));
+        // not dragging, raw motion is ignored
+        inputs.next.push(MouseInput::Motion(Vec2::new(5.0, 5.0)));
+        inputs.update();
+        assert!(!inputs.is_captured());
+        assert_eq!(inputs.cursor_delta, Vec2::ZERO);
+
+        inputs.next.push(MouseInput::Button {
+            button: MouseButton::Left,
+            state: ElementState::Pressed,
+        });
+        inputs.update();
+        assert!(inputs.is_captured());
+
+        // the cursor left the window, only raw motion arrives
+        inputs.next.push(MouseInput::Motion(Vec2::new(-30.0, 10.0)));
+        inputs.next.push(MouseInput::Motion(Vec2::new(-20.0, 0.0)));
+        inputs.update();
+        assert_eq!(inputs.cursor_delta, Vec2::new(-50.0, 10.0));
+        assert_eq!(inputs.cursor_position, Vec2::new(50.0, 110.0));
+
+        // window events take precedence over raw motion
+        inputs
+            .next
+            .push(MouseInput::CursorMoved(Vec2::new(60.0, 110.0)));
+        inputs.next.push(MouseInput::Motion(Vec2::new(12.0, 0.0)));
+        inputs.update();
+        assert_eq!(inputs.cursor_delta, Vec2::new(10.0, 0.0));
+
+        inputs.next.push(MouseInput::Button {
+            button: MouseButton::Left,
+            state: ElementState::Released,
+        });
+        inputs.update();
+        assert!(!inputs.is_captured());
+
+        // the jump back to the real cursor position is not a movement
+        inputs
+            .next
+            .push(MouseInput::CursorMoved(Vec2::new(300.0, 300.0)));
+        inputs.update();
+        assert_eq!(inputs.cursor_delta, Vec2::ZERO);
+        assert_eq!(inputs.cursor_position, Vec2::new(300.0, 300.0));
+    }
+
+    #[test]
+    fn test_scroll_normalization() {
+        let mut inputs = MouseInputs {
+            line_height: 10.0,
+            ..Default::default()
+        };
+        inputs
+            .next
+            .push(MouseInput::Scroll(MouseScrollDelta::LineDelta(0.0, 2.0)));
+        inputs
+            .next
+            .push(MouseInput::Scroll(MouseScrollDelta::PixelDelta(
+                winit::dpi::PhysicalPosition::new(5.0, 3.0),
+            )));
+        inputs.update();
+
+        assert_eq!(inputs.scroll.len(), 2);
+        assert_eq!(inputs.scroll_delta, Vec2::new(5.0, 23.0));
+
+        inputs.update();
+        assert!(inputs.scroll.is_empty());
+        assert_eq!(inputs.scroll_delta, Vec2::ZERO);
+    }
+
+    #[test]
+    fn test_focus_lost_releases_inputs() {
+        let mut keyboard = KeyBoardInputs::default();
+        keyboard.pressed.insert(KeyCode::KeyW);
+        keyboard.pressed.insert(KeyCode::ShiftLeft);
+        keyboard.update();
+        assert!(keyboard.shift());
+
+        keyboard.release_all = true;
+        keyboard.update();
+        assert!(keyboard.pressed.is_empty());
+        assert!(keyboard.just_released.contains(&KeyCode::KeyW));
+        assert!(!keyboard.shift());
+
+        let mut mouse = MouseInputs::default();
+        mouse.next.push(MouseInput::Button {
+            button: MouseButton::Left,
+            state: ElementState::Pressed,
+        });
+        mouse.next.push(MouseInput::FocusLost);
+        mouse.update();
+        assert!(mouse.pressed.is_empty());
+        assert!(mouse.just_released.contains(&MouseButton::Left));
+        assert!(!mouse.just_pressed.contains(&MouseButton::Left));
+    }
+
+    #[test]
+    fn test_last_extracted() {
+        let mut last = LastExtracted::default();
+        assert!(last.is_stale(ExtractionTick(0)));
+
+        last.mark(ExtractionTick(1));
+        assert!(!last.is_stale(ExtractionTick(1)));
+        assert!(last.is_stale(ExtractionTick(2)));
+    }
+
+    #[test]
+    fn test_missed_extraction_keeps_render_entities() {
+        let game_world = Mutex::new(World::new(4));
+        let mut render_world = World::new(4);
+        render_world.insert_resource(ExtractionTick(3));
+        render_world
+            .run_system(|mut cmd: Commands| {
+                cmd.spawn().insert(ExtractionTick(3));
+            })
+            .unwrap();
+
+        let extract = SystemStage::new("extract");
+        {
+            // hold the lock, simulating a long game tick
+            let _lock = game_world.lock();
+            for _ in 0..2 {
+                extract_render_data(&game_world, &mut render_world, &extract);
+            }
         }
+
+        assert_eq!(
+            render_world.get_resource::<ExtractionTick>().copied(),
+            Some(ExtractionTick(3))
+        );
+        render_world
+            .run_system(renderer::gc_system::<camera::CameraUniform>)
+            .unwrap();
+        let n = render_world.run_view_system(|q: Query<&ExtractionTick>| q.iter().count());
+        assert_eq!(n, 1);
     }
 }
@@ -1,8 +1,18 @@
 pub use crate::assets::*;
+pub use crate::events::{Events, EventsPlugin};
 pub use crate::transform::{transform_bundle, GlobalTransform, Transform};
 pub use crate::App;
 pub use crate::DeltaTime;
+pub use crate::ExtractionTick;
+pub use crate::GameLoopControl;
+pub use crate::GameWorld;
+pub use crate::LastExtracted;
+pub use crate::ParallelConfig;
 pub use crate::Plugin;
 pub use crate::Time;
 pub use crate::Timer;
+/// Thread pool of the worlds, available as a resource in both the game and the render world.
+/// Use `JobPool::scope` to spawn jobs that may borrow from the enclosing system.
+#[cfg(feature = "parallel")]
+pub use cecs::prelude::JobPool;
 pub use cecs::prelude::*;
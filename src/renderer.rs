@@ -1,7 +1,17 @@
+#[cfg(feature = "debug-gizmos")]
+pub mod gizmos;
+pub mod sprite_animation;
 pub mod sprite_renderer;
 pub mod texture;
 
-use std::{collections::BTreeSet, marker::PhantomData, sync::Arc};
+use std::{
+    collections::BTreeSet,
+    marker::PhantomData,
+    sync::{
+        atomic::{AtomicBool, AtomicU32, Ordering},
+        Arc,
+    },
+};
 
 use cecs::{
     prelude::*,
@@ -14,40 +24,215 @@ use winit::{dpi::PhysicalSize, window::Window};
 
 pub use crate::camera::camera_bundle;
 use crate::{
-    camera::{CameraBuffer, CameraPlugin, CameraUniform},
+    camera::{upload_camera_uniform, CameraBuffer, CameraPlugin, CameraUniform},
     ExtractionTick, GameWorld, Plugin,
 };
 
 use self::sprite_renderer::SpriteRendererPlugin;
 
 pub struct GraphicsState {
+    /// Color in linear space. When the surface format is sRGB the conversion is done by the GPU,
+    /// the same applies to colors output by shaders.
     pub clear_color: wgpu::Color,
 
     window: Arc<Window>,
     surface: wgpu::Surface<'static>,
+    adapter_info: wgpu::AdapterInfo,
     device: wgpu::Device,
     queue: wgpu::Queue,
     config: wgpu::SurfaceConfiguration,
     size: PhysicalSize<u32>,
 
     camera_bind_group_layout: wgpu::BindGroupLayout,
+    /// Camera used by passes that are independent of the world cameras, e.g. UI
+    screen_camera: wgpu::Buffer,
+    screen_camera_bind_group: wgpu::BindGroup,
 
     depth_texture: texture::Texture,
+
+    /// Incremented every time the surface is reconfigured
+    surface_generation: u32,
+    /// Consecutive frames the surface was lost
+    lost_count: u32,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct RenderPasses(pub BTreeSet<RenderPass>);
 
+/// Render world resource selecting the surface's texture format.
+///
+/// Used if the surface supports it, otherwise the first sRGB format supported is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreferredSurfaceFormat(pub wgpu::TextureFormat);
+
+/// Prefer `preferred`, then sRGB formats, then whatever the surface lists first
+fn select_surface_format(
+    formats: &[wgpu::TextureFormat],
+    preferred: Option<wgpu::TextureFormat>,
+) -> wgpu::TextureFormat {
+    preferred
+        .filter(|f| formats.contains(f))
+        .or_else(|| formats.iter().copied().find(|f| f.is_srgb()))
+        .unwrap_or(formats[0])
+}
+
+/// Game world resource toggling render interpolation.
+///
+/// The game world ticks independently of rendering, drawing the latest tick as-is shows
+/// stepping when the render rate differs from the tick rate. When enabled sprites are drawn
+/// between their positions of the last two ticks, which delays them by up to one tick.
+/// Cameras are not interpolated.
+///
+/// Disabled by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RenderInterpolation {
+    pub enabled: bool,
+}
+
+/// Inner size of the window in physical pixels.
+///
+/// Initialized from the window when it's created and updated on resize. Either dimension is 0
+/// while the window is minimized.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct WindowSize {
     pub width: u32,
     pub height: u32,
 }
 
+impl From<winit::dpi::PhysicalSize<u32>> for WindowSize {
+    fn from(size: winit::dpi::PhysicalSize<u32>) -> Self {
+        Self {
+            width: size.width,
+            height: size.height,
+        }
+    }
+}
+
+/// Game world resource selecting when frames are rendered
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum RedrawMode {
+    /// Render as fast as possible, the usual choice for games
+    #[default]
+    Continuous,
+    /// Render only when a frame was requested via [RedrawRequest], or the window received an
+    /// event, e.g. input or resize. Lowers the idle CPU and GPU usage of tools.
+    ///
+    /// The game world keeps ticking regardless. Changes to the mode are picked up on the next
+    /// rendered frame, request one after switching.
+    OnDemand,
+}
+
+/// Game world resource for requesting a redraw in [RedrawMode::OnDemand].
+///
+/// Requests are cheap and can be made from any system, e.g. while an animation is playing.
+#[derive(Debug, Default, Clone)]
+pub struct RedrawRequest(Arc<AtomicBool>);
+
+impl RedrawRequest {
+    pub fn request(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    /// Returns whether a redraw was requested since the last call
+    pub(crate) fn take(&self) -> bool {
+        self.0.swap(false, Ordering::Relaxed)
+    }
+}
+
+/// Game world resource requesting the OS cursor icon.
+///
+/// Reset to the default icon at the start of every tick, so systems only need to set it
+/// while e.g. an interactive element is hovered.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CursorIconRequest(pub winit::window::CursorIcon);
+
+/// The icon last applied to the window
+#[derive(Debug, Default)]
+struct AppliedCursorIcon(winit::window::CursorIcon);
+
+fn reset_cursor_icon(mut req: ResMut<CursorIconRequest>) {
+    *req = CursorIconRequest::default();
+}
+
+fn apply_cursor_icon(
+    state: Res<GraphicsState>,
+    req: Option<Res<CursorIconRequest>>,
+    mut applied: ResMut<AppliedCursorIcon>,
+) {
+    let icon = req.map(|r| r.0).unwrap_or_default();
+    if applied.0 != icon {
+        state.window().set_cursor(icon);
+        applied.0 = icon;
+    }
+}
+
+/// Bitmask of the layers an entity is rendered on.
+///
+/// Cameras only render entities whose layers intersect with their own. Entities and cameras
+/// without this component are on layer 0.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct RenderLayers(pub u32);
+
+impl Default for RenderLayers {
+    fn default() -> Self {
+        Self::layer(0)
+    }
+}
+
+impl RenderLayers {
+    pub const ALL: Self = Self(u32::MAX);
+
+    pub fn layer(layer: u32) -> Self {
+        debug_assert!(layer < 32);
+        Self(1 << layer)
+    }
+
+    pub fn with(self, layer: u32) -> Self {
+        Self(self.0 | Self::layer(layer).0)
+    }
+
+    pub fn intersects(self, other: Self) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+impl Extract for RenderLayers {
+    type QueryItem = &'static Self;
+    type Filter = ();
+    type Out = (Self,);
+
+    fn extract<'a>(it: <Self::QueryItem as QueryFragment>::Item<'a>) -> Option<Self::Out> {
+        Some((*it,))
+    }
+}
+
 #[derive(Debug)]
 pub struct RenderCommandInput<'a> {
     pub render_pass: &'a mut wgpu::RenderPass<'a>,
     pub camera: &'a wgpu::BindGroup,
+    /// Layers visible to the camera
+    pub layers: RenderLayers,
+    /// Render commands should report the work they submit
+    pub stats: &'a mut RenderStats,
+}
+
+/// Work submitted by the render commands in the last frame
+///
+/// Available in the render world after rendering, and copied into the game world during the
+/// next extraction.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct RenderStats {
+    pub draw_calls: u32,
+    pub instances: u32,
+    pub pipeline_switches: u32,
+    pub bind_group_switches: u32,
+}
+
+impl RenderStats {
+    pub fn draw(&mut self, instances: u32) {
+        self.draw_calls += 1;
+        self.instances += instances;
+    }
 }
 
 pub trait RenderCommand<'a> {
@@ -60,11 +245,17 @@ pub trait RenderCommand<'a> {
 struct RenderCommandInternal {
     pub render_cmd: Arc<dyn Fn(&World, &mut RenderCommandInput) + Send + Sync>,
     pub pass: RenderPass,
+    pub order: i32,
+    /// Registration order, breaks ties between commands of the same order
+    pub seq: u32,
 }
 
 impl RenderCommandInternal {
-    pub fn new<T: RenderCommand<'static> + 'static>(pass: RenderPass) -> Self {
+    pub fn new<T: RenderCommand<'static> + 'static>(pass: RenderPass, order: i32) -> Self {
+        static SEQ: AtomicU32 = AtomicU32::new(0);
         Self {
+            order,
+            seq: SEQ.fetch_add(1, Ordering::Relaxed),
             render_cmd: Arc::new(move |world, input| {
                 world.run_view_system(move |q: T::Parameters| unsafe {
                     let q: &T::Parameters = std::mem::transmute(&q);
@@ -90,6 +281,9 @@ impl Extract for RenderCommandInternal {
 /// RenderCommands are ran on the Render World
 pub struct RenderCommandPlugin<T> {
     pub pass: RenderPass,
+    /// Commands within a pass are executed in ascending order.
+    /// Commands of equal order run in the order they were registered.
+    pub order: i32,
     _m: PhantomData<T>,
 }
 
@@ -97,9 +291,15 @@ impl<T> RenderCommandPlugin<T> {
     pub fn new(pass: RenderPass) -> Self {
         Self {
             pass,
+            order: 0,
             _m: PhantomData,
         }
     }
+
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
 }
 
 impl<T> Plugin for RenderCommandPlugin<T>
@@ -111,14 +311,96 @@ where
             .0
             .insert(self.pass);
         let pass = self.pass;
+        let order = self.order;
         app.add_startup_system(move |mut cmd: Commands| {
-            cmd.spawn().insert(RenderCommandInternal::new::<T>(pass));
+            cmd.spawn()
+                .insert(RenderCommandInternal::new::<T>(pass, order));
         });
     }
 }
 
+/// A render world resource bundling the parts of a custom pipeline, register it via
+/// [CustomRendererPlugin].
+///
+/// See the `mandelbrot` example.
+pub trait CustomRenderer: Component + Sized {
+    /// The pass the renderer draws in
+    const PASS: RenderPass = RenderPass::Transparent;
+
+    /// Create the renderer, ran once on the render world at startup.
+    /// This is where pipelines and buffers are created.
+    fn setup(state: &GraphicsState) -> Self;
+
+    /// Copy the data needed for rendering out of the game world, ran on every extraction.
+    /// The game world is locked for the duration, so keep it short.
+    fn extract(&mut self, _game_world: &World, _tick: ExtractionTick) {}
+
+    fn render(&self, input: &mut RenderCommandInput);
+}
+
+struct CustomRenderCommand<T>(PhantomData<T>);
+
+impl<'a, T: CustomRenderer> RenderCommand<'a> for CustomRenderCommand<T> {
+    type Parameters = Res<'a, T>;
+
+    fn render<'r>(input: &'r mut RenderCommandInput<'a>, renderer: &'r Self::Parameters) {
+        renderer.render(input);
+    }
+}
+
+fn setup_custom_renderer<T: CustomRenderer>(mut cmd: Commands, state: Res<GraphicsState>) {
+    cmd.insert_resource(T::setup(&state));
+}
+
+fn extract_custom_renderer<T: CustomRenderer>(
+    game_world: Res<GameWorld>,
+    tick: Res<ExtractionTick>,
+    renderer: Option<ResMut<T>>,
+) {
+    if let Some(mut renderer) = renderer {
+        renderer.extract(game_world.world(), *tick);
+    }
+}
+
+/// Registers the setup, extraction and render command of a [CustomRenderer]
+pub struct CustomRendererPlugin<T> {
+    /// See [RenderCommandPlugin::order]
+    pub order: i32,
+    _m: PhantomData<T>,
+}
+
+impl<T> Default for CustomRendererPlugin<T> {
+    fn default() -> Self {
+        Self {
+            order: 0,
+            _m: PhantomData,
+        }
+    }
+}
+
+impl<T> CustomRendererPlugin<T> {
+    pub fn with_order(mut self, order: i32) -> Self {
+        self.order = order;
+        self
+    }
+}
+
+impl<T: CustomRenderer> Plugin for CustomRendererPlugin<T> {
+    fn build(self, app: &mut crate::App) {
+        app.add_plugin(
+            RenderCommandPlugin::<CustomRenderCommand<T>>::new(T::PASS).with_order(self.order),
+        );
+        app.add_extract_system(extract_custom_renderer::<T>);
+        app.render_app_mut()
+            .add_startup_system(setup_custom_renderer::<T>);
+    }
+}
+
 impl GraphicsState {
-    pub async fn new(window: Arc<Window>) -> Self {
+    pub async fn new(
+        window: Arc<Window>,
+        preferred_format: Option<PreferredSurfaceFormat>,
+    ) -> Self {
         #[cfg(not(debug_assertions))]
         let flags = InstanceFlags::default();
         #[cfg(debug_assertions)]
@@ -146,6 +428,16 @@ impl GraphicsState {
             .expect("Failed to create adapter");
 
         debug!("Choosen adapter: {:?}", adapter);
+        let adapter_info = adapter.get_info();
+        tracing::info!(
+            name = %adapter_info.name,
+            backend = ?adapter_info.backend,
+            device_type = ?adapter_info.device_type,
+            "Using GPU"
+        );
+        if adapter_info.device_type == wgpu::DeviceType::Cpu {
+            tracing::warn!("Rendering on a software rasterizer, expect poor performance");
+        }
 
         let (device, queue) = adapter
             .request_device(
@@ -167,10 +459,22 @@ impl GraphicsState {
             .await
             .unwrap();
 
+        let format = select_surface_format(
+            &surface.get_capabilities(&adapter).formats,
+            preferred_format.map(|f| f.0),
+        );
+        debug!(?format, "Choosen surface format");
+        if !format.is_srgb() {
+            tracing::warn!(
+                ?format,
+                "Surface format is not sRGB, colors will appear darker than authored"
+            );
+        }
+
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
-            format: surface.get_capabilities(&adapter).formats[0],
-            view_formats: vec![surface.get_capabilities(&adapter).formats[0]],
+            format,
+            view_formats: vec![format],
             width: size.width.max(1),
             height: size.height.max(1),
             // TODO: configure
@@ -182,6 +486,25 @@ impl GraphicsState {
         surface.configure(&device, &config);
 
         let camera_bind_group_layout = device.create_bind_group_layout(&CameraUniform::desc());
+        let screen_camera = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("screen-camera"),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            size: std::mem::size_of::<CameraUniform>() as u64,
+            mapped_at_creation: false,
+        });
+        upload_camera_uniform(
+            &queue,
+            &screen_camera,
+            &CameraUniform::screen_space(config.width, config.height),
+        );
+        let screen_camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: screen_camera.as_entire_binding(),
+            }],
+            label: Some("screen_camera_bind_group"),
+        });
 
         let depth_texture =
             texture::Texture::create_depth_texture(&device, &config, "depth_texture");
@@ -194,6 +517,8 @@ impl GraphicsState {
             config,
             surface,
             camera_bind_group_layout,
+            screen_camera,
+            screen_camera_bind_group,
             clear_color: wgpu::Color {
                 r: 0.4588,
                 g: 0.031,
@@ -201,6 +526,9 @@ impl GraphicsState {
                 a: 1.0,
             },
             window,
+            adapter_info,
+            surface_generation: 0,
+            lost_count: 0,
         }
     }
 
@@ -210,8 +538,14 @@ impl GraphicsState {
             self.config.width = new_size.width;
             self.config.height = new_size.height;
             self.surface.configure(&self.device, &self.config);
+            self.surface_generation += 1;
             self.depth_texture =
                 texture::Texture::create_depth_texture(&self.device, &self.config, "depth_texture");
+            upload_camera_uniform(
+                &self.queue,
+                &self.screen_camera,
+                &CameraUniform::screen_space(new_size.width, new_size.height),
+            );
         }
     }
 
@@ -219,6 +553,17 @@ impl GraphicsState {
         self.size
     }
 
+    /// Changes whenever the surface is reconfigured, e.g. on resize or after the surface was
+    /// lost. Resources depending on the surface can compare it to rebuild when needed.
+    pub fn surface_generation(&self) -> u32 {
+        self.surface_generation
+    }
+
+    /// Number of consecutive frames the surface was lost
+    pub fn lost_count(&self) -> u32 {
+        self.lost_count
+    }
+
     pub fn surface(&self) -> &wgpu::Surface {
         &self.surface
     }
@@ -231,6 +576,26 @@ impl GraphicsState {
         &self.queue
     }
 
+    /// The GPU chosen at startup
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// True if the adapter is a software rasterizer
+    pub fn is_software_adapter(&self) -> bool {
+        self.adapter_info.device_type == wgpu::DeviceType::Cpu
+    }
+
+    /// Features enabled on the device
+    pub fn features(&self) -> wgpu::Features {
+        self.device.features()
+    }
+
+    /// Limits of the device, e.g. `max_texture_dimension_2d`
+    pub fn limits(&self) -> wgpu::Limits {
+        self.device.limits()
+    }
+
     pub fn config(&self) -> &wgpu::SurfaceConfiguration {
         &self.config
     }
@@ -243,6 +608,31 @@ impl GraphicsState {
         &self.window
     }
 
+    /// Raw handle of the window, for integrating native dialogs or overlays.
+    ///
+    /// The render world lives on the event loop's thread. Some platforms (e.g. MacOS) only
+    /// allow using the handle on this thread, so do not send it to the game thread.
+    #[cfg(not(target_family = "wasm"))]
+    pub fn window_handle(
+        &self,
+    ) -> Result<winit::raw_window_handle::WindowHandle<'_>, winit::raw_window_handle::HandleError>
+    {
+        use winit::raw_window_handle::HasWindowHandle;
+        self.window.window_handle()
+    }
+
+    /// Raw handle of the display the window is on.
+    ///
+    /// See [GraphicsState::window_handle] for thread-safety
+    #[cfg(not(target_family = "wasm"))]
+    pub fn display_handle(
+        &self,
+    ) -> Result<winit::raw_window_handle::DisplayHandle<'_>, winit::raw_window_handle::HandleError>
+    {
+        use winit::raw_window_handle::HasDisplayHandle;
+        self.window.display_handle()
+    }
+
     pub fn camera_bind_group_layout(&self) -> &wgpu::BindGroupLayout {
         &self.camera_bind_group_layout
     }
@@ -283,18 +673,39 @@ impl Vertex {
 
 pub type RenderResult = Result<(), wgpu::SurfaceError>;
 
-pub struct RendererPlugin;
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum SurfaceErrorAction {
+    /// Stop the app
+    #[default]
+    Exit,
+    /// Log the error and keep trying to render
+    Log,
+}
 
-fn extract_passes(mut cmd: Commands, game_world: Res<GameWorld>) {
-    cmd.insert_resource(
-        game_world
-            .world()
-            .get_resource::<RenderPasses>()
-            .cloned()
-            .unwrap_or_default(),
-    )
+/// Render world resource controlling how surface errors are handled
+#[derive(Debug, Clone, Copy)]
+pub struct SurfacePolicy {
+    /// Action when the GPU runs out of memory
+    pub on_out_of_memory: SurfaceErrorAction,
+    /// Number of consecutive frames a lost surface is reconfigured. Once exceeded the surface is
+    /// no longer reconfigured, and `on_surface_lost_exhausted` is applied.
+    pub max_reconfigures: u32,
+    /// Action when the surface is still lost after `max_reconfigures` attempts
+    pub on_surface_lost_exhausted: SurfaceErrorAction,
+}
+
+impl Default for SurfacePolicy {
+    fn default() -> Self {
+        Self {
+            on_out_of_memory: SurfaceErrorAction::Exit,
+            on_surface_lost_exhausted: SurfaceErrorAction::Exit,
+            max_reconfigures: 8,
+        }
+    }
 }
 
+pub struct RendererPlugin;
+
 impl Plugin for RendererPlugin {
     fn build(self, app: &mut crate::App) {
         app.render_app_mut().with_stage(crate::Stage::Render, |s| {
@@ -304,10 +715,93 @@ impl Plugin for RendererPlugin {
             width: 0,
             height: 0,
         });
-        app.add_extract_system(extract_passes);
+        app.add_plugin(ExtractResourcePlugin::<RenderPasses>::default());
+        app.insert_resource(CursorIconRequest::default());
+        app.with_stage(crate::Stage::PreUpdate, |s| {
+            s.add_system(reset_cursor_icon);
+        });
+        app.render_app_mut()
+            .insert_resource(AppliedCursorIcon::default());
+        app.render_app_mut().with_stage(crate::Stage::Update, |s| {
+            s.add_system(apply_cursor_icon);
+        });
+        app.add_plugin(ExtractResourcePlugin::<CursorIconRequest>::default());
+        app.add_plugin(ExtractResourcePlugin::<WindowSize>::default());
+        app.add_plugin(ExtractResourcePlugin::<crate::ParallelConfig>::default());
+        app.insert_resource(RenderInterpolation::default());
+        app.insert_resource(RedrawMode::default());
+        app.insert_resource(RedrawRequest::default());
+        app.add_plugin(ExtractResourcePlugin::<RedrawMode>::default());
+        // the request is shared with the render world, so the event loop can read it without
+        // locking the game world
+        app.add_plugin(ExtractResourcePlugin::<RedrawRequest>::default());
+        app.add_plugin(ExtractResourcePlugin::<RenderInterpolation>::default());
+        app.add_plugin(ExtractResourcePlugin::<crate::Time>::default());
+        app.add_plugin(ExtractResourcePlugin::<crate::DeltaTime>::default());
         app.add_plugin(CameraPlugin);
         app.add_plugin(SpriteRendererPlugin);
+        #[cfg(feature = "debug-gizmos")]
+        app.add_plugin(gizmos::GizmoPlugin);
         app.add_plugin(ExtractionPlugin::<RenderCommandInternal>::default());
+        app.add_plugin(ExtractionPlugin::<RenderLayers>::default());
+    }
+}
+
+/// How a pipeline's output is blended with the frame
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BlendMode {
+    /// Colors are not multiplied by their alpha, e.g. most PNG images
+    #[default]
+    Straight,
+    /// Colors are already multiplied by their alpha. Using [BlendMode::Straight] for such
+    /// images results in dark fringes around the edges of transparent regions.
+    Premultiplied,
+    /// Colors are added to the frame, weighted by their alpha. Useful for glow or fire effects.
+    Additive,
+}
+
+impl BlendMode {
+    pub fn blend_state(self) -> wgpu::BlendState {
+        match self {
+            BlendMode::Straight => wgpu::BlendState::ALPHA_BLENDING,
+            BlendMode::Premultiplied => wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING,
+            BlendMode::Additive => wgpu::BlendState {
+                color: wgpu::BlendComponent {
+                    src_factor: wgpu::BlendFactor::SrcAlpha,
+                    dst_factor: wgpu::BlendFactor::One,
+                    operation: wgpu::BlendOperation::Add,
+                },
+                alpha: wgpu::BlendComponent::OVER,
+            },
+        }
+    }
+}
+
+/// Render world resource controlling the first pass of the frame.
+///
+/// If missing the frame is cleared with [GraphicsState::clear_color]. Render passes clear the
+/// depth buffer themselves, regardless of this setting.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClearBehavior {
+    /// Clear the frame with the given linear color
+    Color(wgpu::Color),
+    /// Do not clear, the first pass loads the surface's contents. Use when a background covers
+    /// the entire screen anyway. Most platforms do not preserve the contents of the swapchain
+    /// between frames, so uncovered regions are undefined unless the surface is known to.
+    Load,
+}
+
+impl ClearBehavior {
+    /// Color to clear the frame with, `None` if the frame is not cleared
+    pub fn clear_color(
+        behavior: Option<&ClearBehavior>,
+        default: wgpu::Color,
+    ) -> Option<wgpu::Color> {
+        match behavior {
+            None => Some(default),
+            Some(ClearBehavior::Color(color)) => Some(*color),
+            Some(ClearBehavior::Load) => None,
+        }
     }
 }
 
@@ -318,6 +812,12 @@ pub enum RenderPass {
 }
 
 impl RenderPass {
+    /// Passes that do not depend on the world cameras. These are ran once per frame, using a
+    /// screen space camera.
+    pub fn is_screen_space(self) -> bool {
+        matches!(self, RenderPass::Ui)
+    }
+
     fn begin<'a>(
         self,
         view: &wgpu::TextureView,
@@ -342,7 +842,28 @@ impl RenderPass {
                 view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(state.clear_color),
+                    load: wgpu::LoadOp::Load,
+                    store: StoreOp::Store,
+                },
+            })],
+            depth_stencil_attachment: None,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        })
+    }
+
+    fn begin_clear<'a>(
+        view: &wgpu::TextureView,
+        encoder: &'a mut wgpu::CommandEncoder,
+        color: wgpu::Color,
+    ) -> wgpu::RenderPass<'a> {
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("Clear Pass"),
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(color),
                     store: StoreOp::Store,
                 },
             })],
@@ -364,7 +885,7 @@ impl RenderPass {
                 view,
                 resolve_target: None,
                 ops: wgpu::Operations {
-                    load: wgpu::LoadOp::Clear(state.clear_color),
+                    load: wgpu::LoadOp::Load,
                     store: StoreOp::Store,
                 },
             })],
@@ -387,13 +908,17 @@ fn render_system(mut world: WorldAccess) {
     let result = w.run_view_system(
         |state: Res<GraphicsState>,
          render_passes: Option<Res<RenderPasses>>,
-         cameras: Query<&CameraBuffer>,
+         clear: Option<Res<ClearBehavior>>,
+         cameras: Query<(&CameraBuffer, Option<&RenderLayers>)>,
          render_commands: Query<&RenderCommandInternal>| {
+            let mut stats = RenderStats::default();
             let Some(render_passes) = render_passes else {
                 tracing::trace!("No render pass has been registered");
-                return Ok(());
+                return Ok(stats);
             };
             let cameras = cameras.iter();
+            let mut render_commands = render_commands.iter().collect::<Vec<_>>();
+            render_commands.sort_by_key(|cmd| (cmd.pass, cmd.order, cmd.seq));
             let output = state.surface.get_current_texture()?;
             let view = output
                 .texture
@@ -405,7 +930,12 @@ fn render_system(mut world: WorldAccess) {
                         label: Some("Render Encoder"),
                     });
 
-            for camera_buffer in cameras {
+            if let Some(color) = ClearBehavior::clear_color(clear.as_deref(), state.clear_color) {
+                RenderPass::begin_clear(&view, &mut encoder, color);
+            }
+
+            for (camera_buffer, layers) in cameras {
+                let layers = layers.copied().unwrap_or_default();
                 // FIXME: retain the camera bind ground
                 let camera_bind_group =
                     state.device.create_bind_group(&wgpu::BindGroupDescriptor {
@@ -416,11 +946,13 @@ fn render_system(mut world: WorldAccess) {
                         }],
                         label: Some("camera_bind_group"),
                     });
-                for pass in render_passes.0.iter() {
+                for pass in render_passes.0.iter().filter(|p| !p.is_screen_space()) {
                     let mut render_pass = pass.begin(&view, &mut encoder, &state);
                     let mut input = RenderCommandInput {
                         render_pass: &mut render_pass,
                         camera: &camera_bind_group,
+                        layers,
+                        stats: &mut stats,
                     };
                     for cmd in render_commands.iter().filter(|p| &p.pass == pass) {
                         (cmd.render_cmd)(w, &mut input);
@@ -428,19 +960,47 @@ fn render_system(mut world: WorldAccess) {
                 }
             }
 
+            // screen space passes are drawn once, regardless of the number of cameras
+            for pass in render_passes.0.iter().filter(|p| p.is_screen_space()) {
+                let mut render_pass = pass.begin(&view, &mut encoder, &state);
+                let mut input = RenderCommandInput {
+                    render_pass: &mut render_pass,
+                    camera: &state.screen_camera_bind_group,
+                    layers: RenderLayers::ALL,
+                    stats: &mut stats,
+                };
+                for cmd in render_commands.iter().filter(|p| &p.pass == pass) {
+                    (cmd.render_cmd)(w, &mut input);
+                }
+            }
+
             // submit will accept anything that implements IntoIter
             state.queue.submit(std::iter::once(encoder.finish()));
             output.present();
 
-            Ok(())
+            Ok(stats)
         },
     );
     let w = world.world_mut();
-    // Reconfigure the surface if lost
-    if let Err(wgpu::SurfaceError::Lost) = result {
-        let state = w.get_resource_mut::<GraphicsState>().unwrap();
-        let size = state.size();
-        state.resize(size);
+    let result = result.map(|stats| {
+        w.insert_resource(stats);
+    });
+    let policy = w
+        .get_resource::<SurfacePolicy>()
+        .copied()
+        .unwrap_or_default();
+    let state = w.get_resource_mut::<GraphicsState>().unwrap();
+    match result {
+        // Reconfigure the surface if lost
+        Err(wgpu::SurfaceError::Lost) => {
+            state.lost_count += 1;
+            if state.lost_count <= policy.max_reconfigures {
+                let size = state.size();
+                state.resize(size);
+            }
+        }
+        Ok(_) => state.lost_count = 0,
+        Err(_) => {}
     }
     w.insert_resource(result);
 }
@@ -471,7 +1031,7 @@ fn extractor_system<T: Extract>(
         });
 }
 
-fn gc_system<T: Extract>(
+pub(crate) fn gc_system<T: Extract>(
     mut cmd: Commands,
     q: Query<(EntityId, &ExtractionTick)>,
     tick: Res<ExtractionTick>,
@@ -505,6 +1065,32 @@ where
     }
 }
 
+fn extract_resource_system<T: Component + Clone>(mut cmd: Commands, game_world: Res<GameWorld>) {
+    if let Some(res) = game_world.world().get_resource::<T>() {
+        cmd.insert_resource(res.clone());
+    }
+}
+
+/// Clones the resource `T` from the game world into the render world on every extraction
+pub struct ExtractResourcePlugin<T> {
+    _m: PhantomData<T>,
+}
+
+impl<T> Default for ExtractResourcePlugin<T> {
+    fn default() -> Self {
+        Self { _m: PhantomData }
+    }
+}
+
+impl<T> Plugin for ExtractResourcePlugin<T>
+where
+    T: Component + Clone,
+{
+    fn build(self, app: &mut crate::App) {
+        app.add_extract_system(extract_resource_system::<T>);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::ptr::NonNull;
@@ -560,4 +1146,97 @@ mod tests {
             assert_eq!(n, 1);
         });
     }
+
+    #[test]
+    fn test_extract_resource() {
+        let mut game_world = World::new(4);
+        game_world.insert_resource(WindowSize {
+            width: 42,
+            height: 32,
+        });
+
+        let mut render_world = World::new(4);
+        render_world.insert_resource(GameWorld {
+            world: NonNull::new(&mut game_world).unwrap(),
+        });
+
+        render_world
+            .run_system(extract_resource_system::<WindowSize>)
+            .unwrap();
+
+        let size = render_world.get_resource::<WindowSize>().copied();
+        assert_eq!(
+            size,
+            Some(WindowSize {
+                width: 42,
+                height: 32,
+            })
+        );
+    }
+
+    #[test]
+    fn test_select_surface_format() {
+        use wgpu::TextureFormat as F;
+
+        let formats = [F::Bgra8Unorm, F::Bgra8UnormSrgb, F::Rgba16Float];
+        assert_eq!(select_surface_format(&formats, None), F::Bgra8UnormSrgb);
+        assert_eq!(
+            select_surface_format(&formats, Some(F::Rgba16Float)),
+            F::Rgba16Float
+        );
+        // unsupported preference is ignored
+        assert_eq!(
+            select_surface_format(&formats, Some(F::Rgba8UnormSrgb)),
+            F::Bgra8UnormSrgb
+        );
+        assert_eq!(select_surface_format(&[F::Bgra8Unorm], None), F::Bgra8Unorm);
+    }
+
+    #[test]
+    fn test_blend_mode() {
+        assert_eq!(
+            BlendMode::default().blend_state(),
+            wgpu::BlendState::ALPHA_BLENDING
+        );
+        let additive = BlendMode::Additive.blend_state();
+        assert_eq!(additive.color.dst_factor, wgpu::BlendFactor::One);
+    }
+
+    #[test]
+    fn test_render_layers() {
+        let default = RenderLayers::default();
+        let ui = RenderLayers::layer(1);
+
+        assert!(!default.intersects(ui));
+        assert!(default.with(1).intersects(ui));
+        assert!(RenderLayers::ALL.intersects(default));
+        assert!(RenderLayers::ALL.intersects(ui));
+    }
+
+    #[test]
+    fn test_redraw_request_is_shared() {
+        let game = RedrawRequest::default();
+        // extraction clones the resource into the render world
+        let render = game.clone();
+        assert!(!render.take());
+
+        game.request();
+        game.request();
+        assert!(render.take());
+        assert!(!render.take());
+    }
+
+    #[test]
+    fn test_clear_behavior() {
+        let default = wgpu::Color::BLACK;
+        assert_eq!(ClearBehavior::clear_color(None, default), Some(default));
+        assert_eq!(
+            ClearBehavior::clear_color(Some(&ClearBehavior::Color(wgpu::Color::RED)), default),
+            Some(wgpu::Color::RED)
+        );
+        assert_eq!(
+            ClearBehavior::clear_color(Some(&ClearBehavior::Load), default),
+            None
+        );
+    }
 }
@@ -0,0 +1,334 @@
+use cecs::prelude::*;
+use glam::{Mat3, Vec3, Vec4};
+use wgpu::include_wgsl;
+
+use crate::{camera::ViewFrustum, transform::GlobalTransform, GameWorld, Plugin, Stage};
+
+use super::{
+    sprite_renderer::CullSize, GraphicsState, RenderCommand, RenderCommandInput,
+    RenderCommandPlugin, RenderPass,
+};
+
+const SPHERE_SEGMENTS: usize = 32;
+
+/// Immediate mode debug lines. Lines are cleared at the start of every game tick.
+#[derive(Default)]
+pub struct Gizmos {
+    vertices: Vec<GizmoVertex>,
+    /// Number of times the lines were cleared, so ticks are extracted once
+    generation: u64,
+}
+
+/// Toggles built-in debug drawing
+#[derive(Debug, Default, Clone, Copy)]
+pub struct GizmoSettings {
+    /// Draw the view frustum of every camera
+    pub frustums: bool,
+    /// Draw the culling spheres of sprites
+    pub cull_spheres: bool,
+}
+
+impl Gizmos {
+    pub fn line(&mut self, start: Vec3, end: Vec3, color: Vec4) {
+        let color = color.to_array();
+        self.vertices.push(GizmoVertex {
+            pos: start.to_array(),
+            color,
+        });
+        self.vertices.push(GizmoVertex {
+            pos: end.to_array(),
+            color,
+        });
+    }
+
+    pub fn debug_draw_frustum(&mut self, frustum: &ViewFrustum, color: Vec4) {
+        let [left, right, bot, top, near, far] = frustum.planes;
+        let corner = |a: Vec4, b: Vec4, c: Vec4| -> Vec3 {
+            // solve n·p + w = 0 for all 3 planes
+            let normals = Mat3::from_cols(a.truncate(), b.truncate(), c.truncate()).transpose();
+            -(normals.inverse() * Vec3::new(a.w, b.w, c.w))
+        };
+        let near_corners = [
+            corner(left, bot, near),
+            corner(right, bot, near),
+            corner(right, top, near),
+            corner(left, top, near),
+        ];
+        let far_corners = [
+            corner(left, bot, far),
+            corner(right, bot, far),
+            corner(right, top, far),
+            corner(left, top, far),
+        ];
+        for i in 0..4 {
+            let j = (i + 1) % 4;
+            self.line(near_corners[i], near_corners[j], color);
+            self.line(far_corners[i], far_corners[j], color);
+            self.line(near_corners[i], far_corners[i], color);
+        }
+    }
+
+    pub fn debug_draw_sphere(&mut self, center: Vec3, radius: f32, color: Vec4) {
+        for (u, v) in [(Vec3::X, Vec3::Y), (Vec3::X, Vec3::Z), (Vec3::Y, Vec3::Z)] {
+            let point = |i: usize| {
+                let angle = i as f32 / SPHERE_SEGMENTS as f32 * std::f32::consts::TAU;
+                let (s, c) = angle.sin_cos();
+                center + (u * c + v * s) * radius
+            };
+            for i in 0..SPHERE_SEGMENTS {
+                self.line(point(i), point(i + 1), color);
+            }
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.vertices.clear();
+    }
+}
+
+fn clear_gizmos(mut gizmos: ResMut<Gizmos>) {
+    gizmos.clear();
+    gizmos.generation += 1;
+}
+
+/// Moves the lines of the last tick into the render world. The buffers are swapped, the game
+/// world clears the render world's previous lines at the start of its next tick.
+fn extract_gizmos(
+    mut cmd: Commands,
+    mut game_world: ResMut<GameWorld>,
+    render: Option<ResMut<Gizmos>>,
+) {
+    let Some(game) = game_world.world_mut().get_resource_mut::<Gizmos>() else {
+        return;
+    };
+    match render {
+        // the game did not tick since the last extraction, keep drawing the same lines
+        Some(render) if render.generation == game.generation => {}
+        Some(mut render) => {
+            std::mem::swap(&mut render.vertices, &mut game.vertices);
+            render.generation = game.generation;
+        }
+        None => cmd.insert_resource(Gizmos {
+            vertices: std::mem::take(&mut game.vertices),
+            generation: game.generation,
+        }),
+    }
+}
+
+fn draw_frustums(settings: Res<GizmoSettings>, mut gizmos: ResMut<Gizmos>, q: Query<&ViewFrustum>) {
+    if !settings.frustums {
+        return;
+    }
+    for frustum in q.iter() {
+        gizmos.debug_draw_frustum(frustum, Vec4::new(1.0, 1.0, 0.0, 1.0));
+    }
+}
+
+fn draw_cull_spheres(
+    settings: Res<GizmoSettings>,
+    mut gizmos: ResMut<Gizmos>,
+    q: Query<(&CullSize, &GlobalTransform)>,
+) {
+    if !settings.cull_spheres {
+        return;
+    }
+    for (size, tr) in q.iter() {
+        gizmos.debug_draw_sphere(tr.0.pos, size.0, Vec4::new(0.0, 1.0, 0.0, 1.0));
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+struct GizmoVertex {
+    pos: [f32; 3],
+    color: [f32; 4],
+}
+
+impl GizmoVertex {
+    fn desc<'a>() -> wgpu::VertexBufferLayout<'a> {
+        wgpu::VertexBufferLayout {
+            array_stride: std::mem::size_of::<Self>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &[
+                wgpu::VertexAttribute {
+                    offset: 0,
+                    shader_location: 0,
+                    format: wgpu::VertexFormat::Float32x3,
+                },
+                wgpu::VertexAttribute {
+                    offset: std::mem::size_of::<[f32; 3]>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
+            ],
+        }
+    }
+}
+
+struct GizmoPipeline {
+    render_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    count: u32,
+}
+
+impl GizmoPipeline {
+    fn new(renderer: &GraphicsState) -> Self {
+        let shader = renderer
+            .device()
+            .create_shader_module(include_wgsl!("gizmo-shader.wgsl"));
+
+        let render_pipeline_layout =
+            renderer
+                .device()
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Gizmo Render Pipeline Layout"),
+                    bind_group_layouts: &[renderer.camera_bind_group_layout()],
+                    push_constant_ranges: &[],
+                });
+        let render_pipeline =
+            renderer
+                .device()
+                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+                    label: Some("Gizmo Render Pipeline"),
+                    layout: Some(&render_pipeline_layout),
+                    vertex: wgpu::VertexState {
+                        module: &shader,
+                        entry_point: "vs_main",
+                        buffers: &[GizmoVertex::desc()],
+                        compilation_options: Default::default(),
+                    },
+                    fragment: Some(wgpu::FragmentState {
+                        module: &shader,
+                        entry_point: "fs_main",
+                        compilation_options: Default::default(),
+                        targets: &[Some(wgpu::ColorTargetState {
+                            format: renderer.config().format,
+                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
+                            write_mask: wgpu::ColorWrites::ALL,
+                        })],
+                    }),
+                    primitive: wgpu::PrimitiveState {
+                        topology: wgpu::PrimitiveTopology::LineList,
+                        strip_index_format: None,
+                        front_face: wgpu::FrontFace::Ccw,
+                        cull_mode: None,
+                        polygon_mode: wgpu::PolygonMode::Fill,
+                        unclipped_depth: false,
+                        conservative: false,
+                    },
+                    depth_stencil: Some(wgpu::DepthStencilState {
+                        format: super::texture::Texture::DEPTH_FORMAT,
+                        depth_write_enabled: false,
+                        depth_compare: wgpu::CompareFunction::LessEqual,
+                        stencil: wgpu::StencilState::default(),
+                        bias: wgpu::DepthBiasState::default(),
+                    }),
+                    multisample: wgpu::MultisampleState {
+                        count: 1,
+                        mask: !0,
+                        alpha_to_coverage_enabled: false,
+                    },
+                    multiview: None,
+                    cache: None,
+                });
+
+        let vertex_buffer = renderer.device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gizmo Vertex Buffer"),
+            size: 0,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Self {
+            render_pipeline,
+            vertex_buffer,
+            count: 0,
+        }
+    }
+}
+
+fn update_gizmo_pipeline(
+    renderer: Res<GraphicsState>,
+    gizmos: Option<Res<Gizmos>>,
+    mut pipeline: ResMut<GizmoPipeline>,
+) {
+    let Some(gizmos) = gizmos else {
+        pipeline.count = 0;
+        return;
+    };
+    let bytes = bytemuck::cast_slice::<_, u8>(&gizmos.vertices);
+    let size = bytes.len() as u64;
+    if pipeline.vertex_buffer.size() < size {
+        pipeline.vertex_buffer = renderer.device().create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Gizmo Vertex Buffer"),
+            size: size * 2,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+    }
+    if size > 0 {
+        renderer
+            .queue()
+            .write_buffer(&pipeline.vertex_buffer, 0, bytes);
+    }
+    pipeline.count = gizmos.vertices.len() as u32;
+}
+
+struct GizmoRenderCommand;
+
+impl<'a> RenderCommand<'a> for GizmoRenderCommand {
+    type Parameters = Res<'a, GizmoPipeline>;
+
+    fn render<'r>(
+        RenderCommandInput {
+            render_pass,
+            camera,
+            stats,
+            ..
+        }: &'r mut RenderCommandInput<'a>,
+        pipeline: &'r Self::Parameters,
+    ) {
+        if pipeline.count == 0 {
+            return;
+        }
+        render_pass.set_pipeline(&pipeline.render_pipeline);
+        render_pass.set_bind_group(0, camera, &[]);
+        render_pass.set_vertex_buffer(0, pipeline.vertex_buffer.slice(..));
+        render_pass.draw(0..pipeline.count, 0..1);
+        stats.pipeline_switches += 1;
+        stats.bind_group_switches += 1;
+        stats.draw(1);
+    }
+}
+
+fn setup(mut cmd: Commands, graphics_state: Res<GraphicsState>) {
+    cmd.insert_resource(GizmoPipeline::new(&graphics_state));
+}
+
+pub struct GizmoPlugin;
+
+impl Plugin for GizmoPlugin {
+    fn build(self, app: &mut crate::App) {
+        app.insert_resource(Gizmos::default());
+        app.insert_resource(GizmoSettings::default());
+        app.with_stage(Stage::PreUpdate, |s| {
+            s.add_system(clear_gizmos);
+        });
+        app.with_stage(Stage::Transform, |s| {
+            s.add_system(draw_frustums).add_system(draw_cull_spheres);
+        });
+        app.add_extract_system(extract_gizmos);
+        // draw on top of everything else in the pass
+        app.add_plugin(
+            RenderCommandPlugin::<GizmoRenderCommand>::new(RenderPass::Transparent)
+                .with_order(i32::MAX),
+        );
+
+        if let Some(ref mut app) = app.render_app {
+            app.add_startup_system(setup);
+            app.with_stage(Stage::Update, |s| {
+                s.add_system(update_gizmo_pipeline);
+            });
+        }
+    }
+}
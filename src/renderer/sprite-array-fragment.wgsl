@@ -0,0 +1,10 @@
+@group(1) @binding(0)
+var texture: texture_2d_array<f32>;
+@group(1) @binding(1)
+var texture_sampler: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = textureSample(texture, texture_sampler, in.uv, in.layer);
+    return color;
+}
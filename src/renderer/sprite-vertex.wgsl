@@ -8,16 +8,13 @@ struct Camera {
 @group(0) @binding(0)
 var<uniform> camera: Camera;
 
-@group(1) @binding(0)
-var texture: texture_2d<f32>;
-@group(1) @binding(1)
-var texture_sampler: sampler;
-
 struct SpriteSheet {
     padding: vec2<f32>,
     box_size: vec2<f32>,
     image_size: vec2<f32>,
     num_cols: u32,
+    // 0 if the sheet is a single texture
+    frames_per_layer: u32,
 }
 
 @group(2) @binding(0)
@@ -32,11 +29,16 @@ struct Instance {
     @location(2) pos_scale: vec4<f32>,
     @location(3) sprite_index: u32,
     @location(4) flip: u32,
+    // offset of the quad, in units of its size
+    @location(5) anchor: vec2<f32>,
+    // min.xy, max.xy, empty to sample the sheet's grid
+    @location(6) uv_rect: vec4<f32>,
 }
 
 struct VertexOutput {
     @builtin(position) clip_position: vec4<f32>,
     @location(0) uv: vec2<f32>,
+    @location(1) @interpolate(flat) layer: u32,
 }
 
 fn lerp(a: f32, b: f32, t: f32) -> f32 {
@@ -62,8 +64,16 @@ fn vs_main(
 ) -> VertexOutput {
     var out: VertexOutput;
 
-    let row: u32 = instance.sprite_index / sprite_sheet.num_cols;
-    let col: u32 = instance.sprite_index - sprite_sheet.num_cols * row;
+    var sprite_index = instance.sprite_index;
+    var layer = 0u;
+    if sprite_sheet.frames_per_layer != 0u {
+        layer = sprite_index / sprite_sheet.frames_per_layer;
+        sprite_index = sprite_index % sprite_sheet.frames_per_layer;
+    }
+    out.layer = layer;
+
+    let row: u32 = sprite_index / sprite_sheet.num_cols;
+    let col: u32 = sprite_index - sprite_sheet.num_cols * row;
 
     let offset = sprite_sheet.box_size.xy * vec2<f32>(f32(col), f32(row)) + sprite_sheet.padding;
 
@@ -74,6 +84,9 @@ fn vs_main(
     let box_uv = lerp_vec2(vec2(0.0), sprite_sheet.box_size - sprite_sheet.padding * 2.0, uv) + offset;
     let total_uv = inv_lerp_vec2(vec2(0.0), sprite_sheet.image_size, box_uv);
     out.uv = total_uv;
+    if instance.uv_rect.z > instance.uv_rect.x {
+        out.uv = lerp_vec2(instance.uv_rect.xy, instance.uv_rect.zw, uv);
+    }
 
     // billboarding
     let scale = instance.pos_scale.w;
@@ -81,15 +94,10 @@ fn vs_main(
     let up: vec4<f32> = camera.view_inv[1];
     let right: vec4<f32> = camera.view_inv[0];
 
-    pos += right * model.pos.x * scale;
-    pos += up * model.pos.y * scale;
+    let quad_pos = model.pos.xy - instance.anchor;
+    pos += right * quad_pos.x * scale;
+    pos += up * quad_pos.y * scale;
 
     out.clip_position = camera.view_proj * pos;
     return out;
 }
-
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
-    let color = textureSample(texture, texture_sampler, in.uv);
-    return color;
-}
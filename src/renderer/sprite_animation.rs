@@ -0,0 +1,319 @@
+use std::{io::Cursor, time::Duration};
+
+use anyhow::Context;
+use cecs::prelude::*;
+use glam::Vec2;
+use image::{AnimationDecoder, DynamicImage, Frame, GenericImage, RgbaImage};
+
+use super::{
+    sprite_renderer::{SpriteInstance, SpriteSheet},
+    RedrawRequest,
+};
+use crate::{events::Events, DeltaTime};
+
+/// Zero delays are common in GIFs, browsers treat them as a short delay instead
+const MIN_FRAME_DELAY: Duration = Duration::from_millis(10);
+
+/// Plays the frames of a [SpriteSheet] by advancing the entity's [SpriteInstance].
+///
+/// The animation owns the instance's `index` and `flip`, set them via the animation instead.
+/// Sends [SpriteAnimationEvent]s when marked frames are reached, see [SpriteAnimation::on_frame].
+#[derive(Debug, Clone)]
+pub struct SpriteAnimation {
+    /// Duration of each frame, in order. Delays shorter than 10ms are played as 10ms.
+    pub frame_delays: Vec<Duration>,
+    pub repeat: bool,
+    pub flip: bool,
+    markers: Vec<u32>,
+    current: usize,
+    elapsed: Duration,
+    started: bool,
+    finished_sent: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpriteAnimationEventKind {
+    /// A frame marked via [SpriteAnimation::on_frame] was reached
+    Frame(u32),
+    /// A non-repeating animation reached its end. Sent once.
+    Finished,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SpriteAnimationEvent {
+    pub entity: EntityId,
+    pub kind: SpriteAnimationEventKind,
+}
+
+impl SpriteAnimation {
+    pub fn new(mut frame_delays: Vec<Duration>, repeat: bool) -> Self {
+        for delay in frame_delays.iter_mut() {
+            *delay = (*delay).max(MIN_FRAME_DELAY);
+        }
+        Self {
+            frame_delays,
+            repeat,
+            flip: false,
+            markers: Vec::new(),
+            current: 0,
+            elapsed: Duration::ZERO,
+            started: false,
+            finished_sent: false,
+        }
+    }
+
+    /// Send a [SpriteAnimationEventKind::Frame] event every time `frame` is reached
+    pub fn on_frame(mut self, frame: u32) -> Self {
+        self.markers.push(frame);
+        self
+    }
+
+    pub fn with_flip(mut self, flip: bool) -> Self {
+        self.flip = flip;
+        self
+    }
+
+    pub fn current_frame(&self) -> u32 {
+        self.current as u32
+    }
+
+    pub fn total_duration(&self) -> Duration {
+        (0..self.frame_delays.len()).map(|i| self.delay(i)).sum()
+    }
+
+    /// `frame_delays` is public, so zero delays may still sneak in after construction.
+    /// Those would never advance `elapsed` past a whole loop of the animation.
+    fn delay(&self, frame: usize) -> Duration {
+        self.frame_delays[frame].max(MIN_FRAME_DELAY)
+    }
+
+    /// Returns true once a non-repeating animation played its last frame
+    pub fn finished(&self) -> bool {
+        !self.repeat
+            && !self.frame_delays.is_empty()
+            && self.current + 1 >= self.frame_delays.len()
+            && self.elapsed >= self.delay(self.frame_delays.len() - 1)
+    }
+
+    /// Play the animation from the start
+    pub fn restart(&mut self) {
+        self.current = 0;
+        self.elapsed = Duration::ZERO;
+        self.started = false;
+        self.finished_sent = false;
+    }
+
+    pub fn update(&mut self, dt: Duration) {
+        self.advance(dt, |_| {});
+    }
+
+    /// Same as [SpriteAnimation::update], but reports the events that happened
+    pub fn advance(&mut self, dt: Duration, mut on_event: impl FnMut(SpriteAnimationEventKind)) {
+        if self.frame_delays.is_empty() {
+            return;
+        }
+        if !self.started {
+            self.started = true;
+            self.enter_frame(&mut on_event);
+        }
+        self.elapsed += dt;
+        while self.elapsed >= self.delay(self.current) {
+            if self.current + 1 < self.frame_delays.len() {
+                self.elapsed -= self.delay(self.current);
+                self.current += 1;
+            } else if self.repeat {
+                self.elapsed -= self.delay(self.current);
+                self.current = 0;
+            } else {
+                self.elapsed = self.delay(self.current);
+                if !self.finished_sent {
+                    self.finished_sent = true;
+                    on_event(SpriteAnimationEventKind::Finished);
+                }
+                break;
+            }
+            self.enter_frame(&mut on_event);
+        }
+    }
+
+    fn enter_frame(&self, on_event: &mut impl FnMut(SpriteAnimationEventKind)) {
+        let frame = self.current_frame();
+        if self.markers.contains(&frame) {
+            on_event(SpriteAnimationEventKind::Frame(frame));
+        }
+    }
+}
+
+/// Advances [SpriteAnimation]s, in `Update`. Sprite instances are computed after it.
+pub fn update_sprite_animations(
+    dt: Res<DeltaTime>,
+    mut events: ResMut<Events<SpriteAnimationEvent>>,
+    redraw: Option<Res<RedrawRequest>>,
+    mut q: Query<(EntityId, &mut SpriteAnimation, &mut SpriteInstance)>,
+) {
+    for (entity, animation, instance) in q.iter_mut() {
+        if !animation.finished() {
+            if let Some(redraw) = redraw.as_ref() {
+                redraw.request();
+            }
+        }
+        animation.advance(dt.0, |kind| {
+            events.send(SpriteAnimationEvent { entity, kind })
+        });
+        instance.index = animation.current_frame();
+        instance.flip = animation.flip;
+    }
+}
+
+/// Pack the frames of an animated image into a single [SpriteSheet] grid.
+///
+/// Every frame is stored decompressed, so the sheet takes `frames * width * height * 4` bytes of
+/// both CPU and GPU memory. Long or large animations may also exceed the maximum texture size
+/// of the GPU, such sheets fail to upload, consider downscaling or splitting them.
+///
+/// Fails if the frames differ in size.
+pub fn sprite_sheet_from_frames(
+    frames: Vec<Frame>,
+    repeat: bool,
+) -> anyhow::Result<(SpriteSheet, SpriteAnimation)> {
+    anyhow::ensure!(!frames.is_empty(), "Animation has no frames");
+    let (width, height) = frames[0].buffer().dimensions();
+    if let Some((i, frame)) = frames
+        .iter()
+        .enumerate()
+        .find(|(_, frame)| frame.buffer().dimensions() != (width, height))
+    {
+        anyhow::bail!(
+            "Animation frame {i} is {:?}, but the first frame is {:?}. All frames must have the same size",
+            frame.buffer().dimensions(),
+            (width, height)
+        );
+    }
+    let cols = (frames.len() as f32).sqrt().ceil() as u32;
+    let rows = (frames.len() as u32).div_ceil(cols);
+    let mut image = RgbaImage::new(width * cols, height * rows);
+    let mut delays = Vec::with_capacity(frames.len());
+    for (i, frame) in frames.into_iter().enumerate() {
+        let i = i as u32;
+        delays.push(Duration::from(frame.delay()).max(MIN_FRAME_DELAY));
+        image
+            .copy_from(frame.buffer(), (i % cols) * width, (i / cols) * height)
+            .context("Failed to copy animation frame")?;
+    }
+    let sheet = SpriteSheet::from_grid(DynamicImage::ImageRgba8(image), cols, rows, Vec2::ZERO);
+    Ok((sheet, SpriteAnimation::new(delays, repeat)))
+}
+
+/// Decode an animated PNG
+pub fn load_apng(bytes: &[u8], repeat: bool) -> anyhow::Result<(SpriteSheet, SpriteAnimation)> {
+    let decoder = image::codecs::png::PngDecoder::new(Cursor::new(bytes))?;
+    let frames = decoder.apng()?.into_frames().collect_frames()?;
+    sprite_sheet_from_frames(frames, repeat)
+}
+
+/// Decode an animated GIF
+#[cfg(feature = "gif")]
+pub fn load_gif(bytes: &[u8], repeat: bool) -> anyhow::Result<(SpriteSheet, SpriteAnimation)> {
+    let decoder = image::codecs::gif::GifDecoder::new(Cursor::new(bytes))?;
+    let frames = decoder.into_frames().collect_frames()?;
+    sprite_sheet_from_frames(frames, repeat)
+}
+
+#[cfg(test)]
+mod tests {
+    use image::Delay;
+
+    use super::*;
+
+    fn frames(n: usize, delay_ms: u32) -> Vec<Frame> {
+        (0..n)
+            .map(|_| {
+                Frame::from_parts(
+                    RgbaImage::new(4, 4),
+                    0,
+                    0,
+                    Delay::from_numer_denom_ms(delay_ms, 1),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sheet_from_frames() {
+        let (sheet, animation) = sprite_sheet_from_frames(frames(5, 100), true).unwrap();
+
+        assert_eq!(sheet.size, Vec2::new(12.0, 8.0));
+        assert_eq!(sheet.box_size, Vec2::splat(4.0));
+        assert_eq!(animation.frame_delays.len(), 5);
+        assert_eq!(animation.total_duration(), Duration::from_millis(500));
+    }
+
+    #[test]
+    fn test_frames_must_have_the_same_size() {
+        let mut f = frames(3, 100);
+        f[1] = Frame::from_parts(
+            RgbaImage::new(2, 2),
+            0,
+            0,
+            Delay::from_numer_denom_ms(100, 1),
+        );
+        assert!(sprite_sheet_from_frames(f, true).is_err());
+    }
+
+    #[test]
+    fn test_zero_delay_is_clamped() {
+        let (_, animation) = sprite_sheet_from_frames(frames(2, 0), true).unwrap();
+        assert!(animation.frame_delays.iter().all(|d| *d == MIN_FRAME_DELAY));
+    }
+
+    #[test]
+    fn test_zero_delays_do_not_hang() {
+        let mut animation = SpriteAnimation::new(vec![Duration::ZERO; 3], true);
+        assert!(animation.frame_delays.iter().all(|d| *d == MIN_FRAME_DELAY));
+        animation.update(Duration::from_millis(25));
+        assert_eq!(animation.current_frame(), 2);
+
+        // delays set after construction are clamped when played
+        animation.frame_delays = vec![Duration::ZERO; 3];
+        animation.update(Duration::from_millis(10));
+        assert_eq!(animation.current_frame(), 0);
+    }
+
+    #[test]
+    fn test_animation_update() {
+        let delays = vec![Duration::from_millis(100); 3];
+
+        let mut animation = SpriteAnimation::new(delays.clone(), true);
+        animation.update(Duration::from_millis(250));
+        assert_eq!(animation.current_frame(), 2);
+        animation.update(Duration::from_millis(100));
+        assert_eq!(animation.current_frame(), 0);
+
+        let mut animation = SpriteAnimation::new(delays, false);
+        animation.update(Duration::from_millis(1000));
+        assert_eq!(animation.current_frame(), 2);
+        assert!(animation.finished());
+    }
+
+    #[test]
+    fn test_animation_events() {
+        let mut animation = SpriteAnimation::new(vec![Duration::from_millis(100); 4], false)
+            .on_frame(0)
+            .on_frame(2);
+
+        let mut events = Vec::new();
+        animation.advance(Duration::from_millis(250), |e| events.push(e));
+        assert_eq!(
+            events,
+            [
+                SpriteAnimationEventKind::Frame(0),
+                SpriteAnimationEventKind::Frame(2)
+            ]
+        );
+
+        events.clear();
+        animation.advance(Duration::from_millis(1000), |e| events.push(e));
+        animation.advance(Duration::from_millis(1000), |e| events.push(e));
+        assert_eq!(events, [SpriteAnimationEventKind::Finished]);
+    }
+}
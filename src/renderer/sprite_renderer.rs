@@ -1,21 +1,27 @@
-use image::DynamicImage;
-use std::collections::{BTreeMap, HashMap};
+use anyhow::Context;
+use image::{DynamicImage, GenericImageView};
+use std::{
+    borrow::Cow,
+    collections::{BTreeMap, HashMap, HashSet},
+};
 
 use cecs::prelude::*;
 use glam::Vec2;
-use wgpu::{include_wgsl, util::DeviceExt};
+use wgpu::util::DeviceExt;
 
 use crate::{
-    assets::{AssetId, Assets, AssetsPlugin, Handle, WeakHandle},
-    camera::ViewFrustum,
+    assets::{AssetId, Assets, AssetsPlugin, DecodeAsset, Handle, WeakHandle},
+    camera::{CameraUniform, Ray, ViewFrustum},
+    events::EventsPlugin,
     transform::GlobalTransform,
     GameWorld, Plugin, Stage,
 };
 
 use super::{
+    sprite_animation::{update_sprite_animations, SpriteAnimationEvent},
     texture::{self, Texture},
-    Extract, ExtractionPlugin, GraphicsState, RenderCommand, RenderCommandInput,
-    RenderCommandPlugin, RenderPass, Vertex,
+    BlendMode, Extract, ExtractionPlugin, GraphicsState, RenderCommand, RenderCommandInput,
+    RenderCommandPlugin, RenderInterpolation, RenderLayers, RenderPass, Vertex,
 };
 
 pub fn sprite_sheet_bundle(
@@ -23,17 +29,51 @@ pub fn sprite_sheet_bundle(
     instance: impl Into<Option<SpriteInstance>>,
 ) -> impl Bundle {
     (
-        instance.into().unwrap_or(SpriteInstance {
-            index: 0,
-            flip: false,
-        }),
+        instance.into().unwrap_or_default(),
         Visible,
         handle,
         SpriteInstanceRaw::default(),
     )
 }
 
-struct CullSize(pub f32);
+/// Returns the sprite closest to the ray's origin that the ray hits.
+///
+/// Sprites are billboards facing `camera`, sized by the x component of their scale and offset by
+/// their [SpriteInstance::anchor]. Meant to be called on demand, e.g. on click, see
+/// [crate::camera::CameraUniform::screen_to_ray].
+pub fn pick_sprite<'a>(
+    ray: &Ray,
+    camera: &CameraUniform,
+    sprites: impl IntoIterator<Item = (EntityId, &'a GlobalTransform, &'a SpriteInstance)>,
+) -> Option<EntityId> {
+    let (right, up, forward) = camera.axes();
+    let denom = ray.direction.dot(forward);
+    if denom.abs() <= f32::EPSILON {
+        return None;
+    }
+    let mut closest = None;
+    let mut closest_t = f32::INFINITY;
+    for (id, tr, sprite) in sprites {
+        let pos = tr.0.pos;
+        // the quad lies in the plane facing the camera
+        let t = (pos - ray.origin).dot(forward) / denom;
+        if t < 0.0 || t >= closest_t {
+            continue;
+        }
+        let size = tr.0.scale.x;
+        // same offset as the vertex shader: quad_pos = pos - anchor
+        let center = pos - (right * sprite.anchor.x + up * sprite.anchor.y) * size;
+        let d = ray.at(t) - center;
+        let half_size = size * 0.5;
+        if d.dot(right).abs() <= half_size && d.dot(up).abs() <= half_size {
+            closest = Some(id);
+            closest_t = t;
+        }
+    }
+    closest
+}
+
+pub(crate) struct CullSize(pub f32);
 struct Visible;
 
 fn signed_dist_plane_point(plane: &glam::Vec4, pos: glam::Vec3) -> f32 {
@@ -84,7 +124,10 @@ fn insert_missing_cull(
     mut cmd: Commands,
 ) {
     for (id, handle) in q.iter() {
-        let sheet = assets.get(handle);
+        let Some(sheet) = assets.try_get(handle) else {
+            tracing::trace!(?id, "Sprite references a removed sheet");
+            continue;
+        };
         cmd.entity(id)
             .insert(CullSize(sheet.box_size.x.max(sheet.box_size.y)));
     }
@@ -98,8 +141,22 @@ pub struct SpriteSheet {
     /// Number of boxes in a row
     pub num_cols: u32,
     pub image: DynamicImage,
+    /// Additional images of the same size as `image`.
+    /// If not empty the sheet is uploaded as a texture array, and sprite indices continue on
+    /// the next layer once a layer's boxes are exhausted.
+    pub layers: Vec<DynamicImage>,
     /// Size of the entire sheet
     pub size: Vec2,
+    /// Sampling outside of the sheet's image. Defaults to `ClampToEdge`.
+    ///
+    /// Sprites only sample inside their box, other modes are useful for custom pipelines that
+    /// tile the image, e.g. backgrounds.
+    pub address_mode: wgpu::AddressMode,
+    /// Generate mipmaps for the sheet, so sprites drawn much smaller than their texture do not
+    /// shimmer. Defaults to `false`, pixel art wants sharp texels.
+    ///
+    /// Lower mip levels blend neighbouring boxes, use `padding` to separate them.
+    pub generate_mips: bool,
 }
 
 impl SpriteSheet {
@@ -110,7 +167,100 @@ impl SpriteSheet {
             num_cols,
             size: Vec2::new(image.width() as f32, image.height() as f32),
             image,
+            layers: Vec::new(),
+            address_mode: wgpu::AddressMode::ClampToEdge,
+            generate_mips: false,
+        }
+    }
+
+    pub fn with_address_mode(mut self, address_mode: wgpu::AddressMode) -> Self {
+        self.address_mode = address_mode;
+        self
+    }
+
+    pub fn with_mips(mut self, generate_mips: bool) -> Self {
+        self.generate_mips = generate_mips;
+        self
+    }
+
+    /// Split `image` into a grid of `cols` x `rows` boxes of equal size
+    pub fn from_grid(image: DynamicImage, cols: u32, rows: u32, padding: Vec2) -> Self {
+        assert!(cols > 0 && rows > 0, "SpriteSheet grid must not be empty");
+        if image.width() % cols != 0 || image.height() % rows != 0 {
+            tracing::warn!(
+                width = image.width(),
+                height = image.height(),
+                cols,
+                rows,
+                "SpriteSheet image does not divide evenly into the grid"
+            );
         }
+        let box_size = Vec2::new(
+            (image.width() / cols) as f32,
+            (image.height() / rows) as f32,
+        );
+        Self::from_image(padding, box_size, cols, image)
+    }
+
+    /// Every image is a layer of the sheet.
+    ///
+    /// Fails if `images` is empty, the images differ in size, or there are more than
+    /// [MAX_SHEET_LAYERS] of them.
+    pub fn from_layers(
+        padding: Vec2,
+        box_size: Vec2,
+        num_cols: u32,
+        mut images: Vec<DynamicImage>,
+    ) -> anyhow::Result<Self> {
+        anyhow::ensure!(!images.is_empty(), "SpriteSheet requires at least 1 image");
+        anyhow::ensure!(
+            images.len() <= MAX_SHEET_LAYERS as usize,
+            "SpriteSheet has {} layers, at most {MAX_SHEET_LAYERS} are supported",
+            images.len()
+        );
+        let size = images[0].dimensions();
+        if let Some((i, img)) = images
+            .iter()
+            .enumerate()
+            .find(|(_, img)| img.dimensions() != size)
+        {
+            anyhow::bail!(
+                "SpriteSheet layer {i} is {:?}, but the first layer is {size:?}. All layers must have the same size",
+                img.dimensions()
+            );
+        }
+        let image = images.remove(0);
+        Ok(Self {
+            layers: images,
+            ..Self::from_image(padding, box_size, num_cols, image)
+        })
+    }
+
+    pub fn is_layered(&self) -> bool {
+        !self.layers.is_empty()
+    }
+
+    /// Number of boxes in a single layer
+    fn frames_per_layer(&self) -> u32 {
+        let num_rows = (self.size.y / self.box_size.y) as u32;
+        self.num_cols * num_rows
+    }
+
+    /// Total number of boxes, including every layer
+    pub fn frame_count(&self) -> u32 {
+        self.frames_per_layer() * (1 + self.layers.len() as u32)
+    }
+
+    /// Returns the `(min, max)` UV coordinates of the box at `index`, within its layer.
+    ///
+    /// Matches the coordinates the sprite shader samples.
+    pub fn uv_rect(&self, index: u32) -> (Vec2, Vec2) {
+        let index = index % self.frames_per_layer().max(1);
+        let row = index / self.num_cols;
+        let col = index % self.num_cols;
+        let min = self.box_size * Vec2::new(col as f32, row as f32) + self.padding;
+        let max = min + self.box_size - self.padding * 2.0;
+        (min / self.size, max / self.size)
     }
 
     fn extract(&self) -> SpriteSheetGpu {
@@ -119,15 +269,180 @@ impl SpriteSheet {
             box_size: self.box_size.to_array(),
             num_cols: self.num_cols,
             size: self.size.to_array(),
-            _pad: 0xDEADBEEF,
+            frames_per_layer: if self.is_layered() {
+                self.frames_per_layer()
+            } else {
+                0
+            },
+        }
+    }
+}
+
+/// Decoding settings of a [SpriteSheet], see [SpriteSheet::from_grid]
+#[derive(Debug, Clone, Copy)]
+pub struct SpriteSheetGrid {
+    pub cols: u32,
+    pub rows: u32,
+    pub padding: Vec2,
+}
+
+impl Default for SpriteSheetGrid {
+    fn default() -> Self {
+        Self {
+            cols: 1,
+            rows: 1,
+            padding: Vec2::ZERO,
         }
     }
 }
 
+impl DecodeAsset for SpriteSheet {
+    type Settings = SpriteSheetGrid;
+
+    fn decode(bytes: &[u8], grid: &Self::Settings) -> anyhow::Result<Self> {
+        let image = DynamicImage::decode(bytes, &())?;
+        Ok(Self::from_grid(image, grid.cols, grid.rows, grid.padding))
+    }
+}
+
 #[derive(Default, Debug, Clone, Copy)]
 pub struct SpriteInstance {
     pub index: u32,
     pub flip: bool,
+    /// Point of the sprite placed at the entity's position, relative to the sprite's center and
+    /// in units of its size. E.g. `(0, -0.5)` is the bottom-center, `(-0.5, 0.5)` the top-left
+    /// corner. Defaults to the center.
+    pub anchor: Vec2,
+    /// `(min, max)` UV coordinates to sample, overriding the sheet's grid. Useful for atlases
+    /// packed by external tools, where sprites are not laid out in a grid.
+    ///
+    /// `index` still selects the layer of layered sheets. The quad stays square, so the rect
+    /// should match the sprite's aspect ratio.
+    pub uv_rect: Option<(Vec2, Vec2)>,
+}
+
+impl SpriteInstance {
+    pub fn new(index: u32) -> Self {
+        Self {
+            index,
+            ..Default::default()
+        }
+    }
+
+    pub fn with_anchor(mut self, anchor: Vec2) -> Self {
+        self.anchor = anchor;
+        self
+    }
+
+    pub fn with_uv_rect(mut self, min: Vec2, max: Vec2) -> Self {
+        self.uv_rect = Some((min, max));
+        self
+    }
+}
+
+/// Most layers a [SpriteSheet] may have, wgpu's default `max_texture_array_layers`
+pub const MAX_SHEET_LAYERS: u32 = 256;
+
+/// Material of sprites without a `Handle<SpriteMaterial>`
+const DEFAULT_MATERIAL: AssetId = AssetId::MAX;
+
+/// Custom fragment shader for sprites, add a `Handle<SpriteMaterial>` to a sprite entity to
+/// draw it with the material.
+///
+/// Sprites of every material share the vertex shader and instance data of the sprite renderer.
+/// Sprites of different materials are drawn in separate batches.
+///
+/// Materials are not supported for layered sheets, see [SpriteSheet::from_layers]. Their
+/// sprites are drawn with the built-in shader and a warning is logged once.
+pub struct SpriteMaterial {
+    /// WGSL source of the fragment stage, the entry point is `fs_main`.
+    ///
+    /// The source is appended to the sprite vertex shader, so it can use its `VertexOutput`
+    /// and `sprite_sheet` uniform. The sheet's texture and sampler must be declared at
+    /// `@group(1) @binding(0)` and `@group(1) @binding(1)`, see [SpriteMaterial::default].
+    pub fragment_shader: Cow<'static, str>,
+    /// Contents of the uniform buffer bound to `@group(3) @binding(0)`, if any.
+    ///
+    /// Changes are uploaded every frame, but the size must not change.
+    pub uniform: Option<Vec<u8>>,
+    /// Use [BlendMode::Premultiplied] for premultiplied textures. Changes are not applied
+    /// after the material is loaded.
+    pub blend: BlendMode,
+}
+
+impl Default for SpriteMaterial {
+    /// Same as the built-in sprite shader
+    fn default() -> Self {
+        Self::new(include_str!("sprite-fragment.wgsl"))
+    }
+}
+
+impl SpriteMaterial {
+    pub fn new(fragment_shader: impl Into<Cow<'static, str>>) -> Self {
+        Self {
+            fragment_shader: fragment_shader.into(),
+            uniform: None,
+            blend: BlendMode::default(),
+        }
+    }
+
+    pub fn with_blend(mut self, blend: BlendMode) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    pub fn with_uniform<T: bytemuck::Pod>(mut self, value: &T) -> Self {
+        self.set_uniform(value);
+        self
+    }
+
+    pub fn set_uniform<T: bytemuck::Pod>(&mut self, value: &T) {
+        self.uniform = Some(bytemuck::bytes_of(value).to_vec());
+    }
+}
+
+impl Extract for Handle<SpriteMaterial> {
+    type QueryItem = &'static Handle<SpriteMaterial>;
+
+    type Filter = With<SpriteInstanceRaw>;
+
+    type Out = (WeakHandle<SpriteMaterial>,);
+
+    fn extract<'a>(
+        handle: <Self::QueryItem as cecs::query::QueryFragment>::Item<'a>,
+    ) -> Option<Self::Out> {
+        Some((handle.downgrade(),))
+    }
+}
+
+fn add_missing_materials(
+    mut pipeline: ResMut<SpritePipeline>,
+    renderer: Res<GraphicsState>,
+    game_world: Res<GameWorld>,
+) {
+    game_world
+        .world()
+        .run_view_system(|materials: Res<crate::assets::Assets<SpriteMaterial>>| {
+            for (id, material) in materials.iter() {
+                match pipeline.materials.get(&id) {
+                    None => pipeline.add_material(id, material, &renderer),
+                    Some(MaterialPipeline {
+                        uniform: Some((buffer, _)),
+                        ..
+                    }) => {
+                        if let Some(contents) = material.uniform.as_ref() {
+                            if contents.len() as u64 == buffer.size() {
+                                renderer.queue().write_buffer(buffer, 0, contents);
+                            } else {
+                                tracing::warn!(id, "SpriteMaterial uniform changed size, ignoring");
+                            }
+                        }
+                    }
+                    Some(_) => {}
+                }
+            }
+            pipeline.materials.retain(|id, _| materials.contains(*id));
+        });
 }
 
 pub fn add_missing_sheets(
@@ -139,10 +454,17 @@ pub fn add_missing_sheets(
         .world()
         .run_view_system(|sheets: Res<crate::assets::Assets<SpriteSheet>>| {
             for (id, sheet) in sheets.iter() {
-                if !pipeline.sheets.contains_key(&id) {
-                    pipeline.add_sheet(id, sheet, &renderer);
+                if pipeline.sheets.contains_key(&id) || pipeline.failed_sheets.contains(&id) {
+                    continue;
+                }
+                if let Err(err) = pipeline.add_sheet(id, sheet, &renderer) {
+                    tracing::error!(id, "Failed to upload SpriteSheet, skipping it: {err:#}");
+                    pipeline.failed_sheets.insert(id);
                 }
             }
+            // sheets may be removed from the assets while handles are still alive
+            pipeline.sheets.retain(|id, _| sheets.contains(*id));
+            pipeline.failed_sheets.retain(|id| sheets.contains(*id));
         });
 }
 
@@ -159,7 +481,7 @@ fn unload_sheets(
         .collect::<Vec<_>>();
     for id in unloaded {
         pipeline.unload_sheet(id);
-        instances.0.remove(&id);
+        instances.0.retain(|(sheet_id, _, _), _| *sheet_id != id);
         handles.0.remove(&id);
     }
 }
@@ -181,12 +503,17 @@ fn compute_sprite_instances(
             index: i.index,
             pos_scale: [pos.x, pos.y, pos.z, scale.x],
             flip: i.flip as u32,
+            anchor: i.anchor.to_array(),
+            uv_rect: i
+                .uv_rect
+                .map(|(min, max)| [min.x, min.y, max.x, max.y])
+                .unwrap_or_default(),
         };
     });
 }
 
 #[derive(Default)]
-struct SpritePipelineInstances(BTreeMap<u64, Vec<SpriteInstanceRaw>>);
+struct SpritePipelineInstances(InstanceBins);
 
 fn clear_pipeline_instances(mut instances: ResMut<SpritePipelineInstances>) {
     for i in instances.0.values_mut() {
@@ -194,6 +521,38 @@ fn clear_pipeline_instances(mut instances: ResMut<SpritePipelineInstances>) {
     }
 }
 
+/// Remove the bins no sprite was gathered into
+fn prune_instance_bins(bins: &mut InstanceBins) {
+    bins.retain(|_, instances| !instances.is_empty());
+}
+
+/// Instance buffers of sheets or layers without sprites are freed after this many frames
+const INSTANCE_BUFFER_IDLE_FRAMES: u32 = 300;
+/// Instance buffers are shrunk after this many frames using less than a quarter of their capacity
+const INSTANCE_BUFFER_SHRINK_FRAMES: u32 = 300;
+
+/// Returns the new capacity of an instance buffer if it has to be reallocated
+fn instance_buffer_capacity(
+    capacity: u64,
+    required: u64,
+    low_usage_frames: &mut u32,
+) -> Option<u64> {
+    if capacity < required {
+        *low_usage_frames = 0;
+        return Some(required * 2);
+    }
+    if capacity <= required * 4 {
+        *low_usage_frames = 0;
+        return None;
+    }
+    *low_usage_frames += 1;
+    if *low_usage_frames < INSTANCE_BUFFER_SHRINK_FRAMES {
+        return None;
+    }
+    *low_usage_frames = 0;
+    Some(required * 2)
+}
+
 impl Extract for SpriteInstanceRaw {
     type QueryItem = (&'static Handle<SpriteSheet>, &'static SpriteInstanceRaw);
 
@@ -208,62 +567,280 @@ impl Extract for SpriteInstanceRaw {
     }
 }
 
+/// Render world component holding the sprite's position and scale of the last two game ticks
+struct SpriteMotion {
+    prev: [f32; 4],
+    curr: [f32; 4],
+    /// Game tick `curr` was extracted in
+    tick: instant::Instant,
+}
+
+fn insert_missing_sprite_motion(
+    mut cmd: Commands,
+    time: Option<Res<crate::Time>>,
+    q: Query<(EntityId, &SpriteInstanceRaw), WithOut<SpriteMotion>>,
+) {
+    let Some(time) = time else {
+        return;
+    };
+    for (id, raw) in q.iter() {
+        cmd.entity(id).insert(SpriteMotion {
+            prev: raw.pos_scale,
+            curr: raw.pos_scale,
+            tick: time.0,
+        });
+    }
+}
+
+/// Progress of `now` from the latest game tick towards the next one, in `[0, 1]`
+fn interpolation_alpha(
+    tick: instant::Instant,
+    dt: std::time::Duration,
+    now: instant::Instant,
+) -> f32 {
+    let dt = dt.as_secs_f32();
+    if dt <= 0.0 {
+        return 1.0;
+    }
+    (now.saturating_duration_since(tick).as_secs_f32() / dt).min(1.0)
+}
+
+fn interpolate_sprites(
+    config: Option<Res<RenderInterpolation>>,
+    time: Option<Res<crate::Time>>,
+    dt: Option<Res<crate::DeltaTime>>,
+    mut q: Query<(&mut SpriteInstanceRaw, &mut SpriteMotion)>,
+) {
+    let (Some(time), Some(dt)) = (time, dt) else {
+        return;
+    };
+    let enabled = config.map(|c| *c).unwrap_or_default().enabled;
+    let alpha = interpolation_alpha(time.0, dt.0, instant::Instant::now());
+    for (raw, motion) in q.iter_mut() {
+        // the instance holds freshly extracted data only if the game ticked since,
+        // otherwise it may hold the previous frame's interpolated position
+        if motion.tick != time.0 {
+            motion.prev = motion.curr;
+            motion.curr = raw.pos_scale;
+            motion.tick = time.0;
+        }
+        if !enabled {
+            raw.pos_scale = motion.curr;
+            continue;
+        }
+        for i in 0..4 {
+            raw.pos_scale[i] = motion.prev[i] + (motion.curr[i] - motion.prev[i]) * alpha;
+        }
+    }
+}
+
+/// Instances binned by (sheet, material, render layers)
+type InstanceBins = BTreeMap<(AssetId, AssetId, u32), Vec<SpriteInstanceRaw>>;
+
+fn instance_bin_key(
+    sheet: &WeakHandle<SpriteSheet>,
+    layers: Option<&RenderLayers>,
+    material: Option<&WeakHandle<SpriteMaterial>>,
+) -> (AssetId, AssetId, u32) {
+    (
+        sheet.id(),
+        material.map(|m| m.id()).unwrap_or(DEFAULT_MATERIAL),
+        layers.copied().unwrap_or_default().0,
+    )
+}
+
+/// Number of instances a single gather job bins
+#[cfg(feature = "parallel")]
+const GATHER_CHUNK_SIZE: usize = 4096;
+
+/// Instances keep the order of the query within their bin, regardless of `single_threaded`.
+/// Sprites are not depth sorted, overlapping translucent sprites of the same bin are drawn in
+/// this order.
+#[cfg(feature = "parallel")]
+fn gather_instances(
+    q: &Query<(
+        &WeakHandle<SpriteSheet>,
+        &SpriteInstanceRaw,
+        Option<&RenderLayers>,
+        Option<&WeakHandle<SpriteMaterial>>,
+    )>,
+    out: &mut InstanceBins,
+    single_threaded: bool,
+    pool: &JobPool,
+) {
+    if single_threaded {
+        gather_instances_serial(q, out);
+        return;
+    }
+    // every job fills its own bins, which are merged in chunk order afterwards
+    let items = q.iter().collect::<Vec<_>>();
+    let mut chunk_bins = Vec::new();
+    chunk_bins.resize_with(
+        items.len().div_ceil(GATHER_CHUNK_SIZE),
+        InstanceBins::default,
+    );
+    pool.scope(|s| {
+        for (chunk, bins) in items.chunks(GATHER_CHUNK_SIZE).zip(chunk_bins.iter_mut()) {
+            s.spawn(move |_s| {
+                for (handle, raw, layers, material) in chunk {
+                    let key = instance_bin_key(handle, *layers, *material);
+                    bins.entry(key).or_default().push(**raw);
+                }
+            });
+        }
+    });
+    for bins in chunk_bins {
+        for (key, instances) in bins {
+            out.entry(key).or_default().extend_from_slice(&instances);
+        }
+    }
+}
+
+fn gather_instances_serial(
+    q: &Query<(
+        &WeakHandle<SpriteSheet>,
+        &SpriteInstanceRaw,
+        Option<&RenderLayers>,
+        Option<&WeakHandle<SpriteMaterial>>,
+    )>,
+    out: &mut InstanceBins,
+) {
+    for (handle, raw, layers, material) in q.iter() {
+        let key = instance_bin_key(handle, layers, material);
+        out.entry(key).or_default().push(*raw);
+    }
+}
+
 fn update_sprite_pipelines(
     renderer: Res<GraphicsState>,
-    q: Query<(&WeakHandle<SpriteSheet>, &SpriteInstanceRaw)>,
+    q: Query<(
+        &WeakHandle<SpriteSheet>,
+        &SpriteInstanceRaw,
+        Option<&RenderLayers>,
+        Option<&WeakHandle<SpriteMaterial>>,
+    )>,
     mut pipeline: ResMut<SpritePipeline>,
     mut instances: ResMut<SpritePipelineInstances>,
+    #[cfg(feature = "parallel")] config: Option<Res<crate::ParallelConfig>>,
+    #[cfg(feature = "parallel")] pool: Res<JobPool>,
 ) {
-    for (handle, raw) in q.iter() {
-        instances.0.entry(handle.id()).or_default().push(*raw);
+    #[cfg(feature = "parallel")]
+    {
+        let single_threaded = config.map(|c| c.single_threaded).unwrap_or(false);
+        gather_instances(&q, &mut instances.0, single_threaded, &pool);
+    }
+    #[cfg(not(feature = "parallel"))]
+    gather_instances_serial(&q, &mut instances.0);
+    prune_instance_bins(&mut instances.0);
+
+    for sheet in pipeline.sheets.values_mut() {
+        for buffer in sheet.instances.values_mut() {
+            buffer.count = 0;
+        }
     }
 
-    for (id, cpu) in instances.0.iter() {
+    let mut uploaded = 0;
+    for ((id, material, layers), cpu) in instances.0.iter() {
         let Some(sprite_rendering_data) = pipeline.sheets.get_mut(&id) else {
             continue;
         };
+        let sprite_rendering_data = sprite_rendering_data
+            .instances
+            .entry((*material, *layers))
+            .or_insert_with(|| SpriteInstanceBuffer::new(&renderer, *id));
 
+        sprite_rendering_data.count = cpu.len();
+        sprite_rendering_data.idle_frames = 0;
         let instance_data_bytes = bytemuck::cast_slice::<_, u8>(&cpu);
         let size = instance_data_bytes.len() as u64;
-        if sprite_rendering_data.instance_gpu.size() < size {
-            // resize the buffer
+        // the buffer's size is its capacity, only the used range is written
+        // so fluctuating counts do not reallocate
+        if let Some(capacity) = instance_buffer_capacity(
+            sprite_rendering_data.instance_gpu.size(),
+            size,
+            &mut sprite_rendering_data.low_usage_frames,
+        ) {
             sprite_rendering_data.instance_gpu =
                 renderer.device.create_buffer(&wgpu::BufferDescriptor {
                     label: Some(&format!("Sprite Instance Buffer - {}", id)),
-                    size: size * 2,
+                    size: capacity,
                     usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
                     mapped_at_creation: false,
                 });
         }
-        renderer.queue.write_buffer(
-            &sprite_rendering_data.instance_gpu,
-            0,
-            bytemuck::cast_slice(&cpu),
-        );
-        sprite_rendering_data.count = cpu.len();
+        renderer
+            .queue
+            .write_buffer(&sprite_rendering_data.instance_gpu, 0, instance_data_bytes);
+        uploaded += size;
+    }
+    tracing::trace!(bytes = uploaded, "Uploaded sprite instances");
+
+    for sheet in pipeline.sheets.values_mut() {
+        sheet.instances.retain(|_, buffer| {
+            if buffer.count == 0 {
+                buffer.idle_frames += 1;
+            }
+            buffer.idle_frames < INSTANCE_BUFFER_IDLE_FRAMES
+        });
     }
 }
 
 #[derive(Default)]
 struct RenderSpritesheetHandles(pub HashMap<AssetId, WeakHandle<SpriteSheet>>);
 
-// per spritesheet
-pub struct SpriteRenderingData {
+pub struct SpriteInstanceBuffer {
     pub count: usize,
     pub instance_gpu: wgpu::Buffer,
+    /// Consecutive frames without instances
+    idle_frames: u32,
+    low_usage_frames: u32,
+}
+
+impl SpriteInstanceBuffer {
+    fn new(renderer: &GraphicsState, id: AssetId) -> Self {
+        Self {
+            count: 0,
+            idle_frames: 0,
+            low_usage_frames: 0,
+            instance_gpu: renderer.device.create_buffer(&wgpu::BufferDescriptor {
+                label: Some(&format!("Sprite Instance Buffer - {}", id)),
+                mapped_at_creation: false,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                size: 0,
+            }),
+        }
+    }
+}
+
+// per spritesheet
+pub struct SpriteRenderingData {
+    /// Instances per (material, render layer mask)
+    pub instances: BTreeMap<(AssetId, u32), SpriteInstanceBuffer>,
     pub spritesheet_gpu: wgpu::BindGroup,
     pub spritesheet_bind_group: wgpu::BindGroup,
     pub texture: Texture,
+    pub layered: bool,
 }
 
 pub struct SpritePipeline {
     sheets: HashMap<AssetId, SpriteRenderingData>,
+    /// Sheets that could not be uploaded, not retried until they're removed
+    failed_sheets: HashSet<AssetId>,
+    materials: HashMap<AssetId, MaterialPipeline>,
     // shared
     render_pipeline: wgpu::RenderPipeline,
+    array_render_pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     num_indices: u32,
     sprite_sheet_layout: wgpu::BindGroupLayout,
+    texture_bind_group_layout: wgpu::BindGroupLayout,
+    material_layout: wgpu::BindGroupLayout,
+}
+
+struct MaterialPipeline {
+    pipeline: wgpu::RenderPipeline,
+    uniform: Option<(wgpu::Buffer, wgpu::BindGroup)>,
 }
 
 #[repr(C)]
@@ -273,7 +850,7 @@ struct SpriteSheetGpu {
     pub box_size: [f32; 2],
     pub size: [f32; 2],
     pub num_cols: u32,
-    pub _pad: u32,
+    pub frames_per_layer: u32,
 }
 
 impl SpritePipeline {
@@ -281,11 +858,137 @@ impl SpritePipeline {
         self.sheets.remove(&id);
     }
 
-    pub fn add_sheet(&mut self, id: AssetId, sheet: &SpriteSheet, renderer: &GraphicsState) {
-        let texture = Texture::from_image(renderer.device(), renderer.queue(), &sheet.image, None)
-            .expect("Failed to create texture");
+    pub fn add_material(
+        &mut self,
+        id: AssetId,
+        material: &SpriteMaterial,
+        renderer: &GraphicsState,
+    ) {
+        let label = format!("Sprite Material {id}");
+        let shader = renderer
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some(&label),
+                source: wgpu::ShaderSource::Wgsl(
+                    format!(
+                        "{}{}",
+                        include_str!("sprite-vertex.wgsl"),
+                        material.fragment_shader
+                    )
+                    .into(),
+                ),
+            });
+        let mut layouts = vec![&self.texture_bind_group_layout, &self.sprite_sheet_layout];
+        if material.uniform.is_some() {
+            layouts.push(&self.material_layout);
+        }
+        let pipeline =
+            create_sprite_render_pipeline(renderer, &shader, &layouts, material.blend, &label);
+        let uniform = material.uniform.as_ref().map(|contents| {
+            let buffer = renderer
+                .device
+                .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some(&format!("{label} Uniform")),
+                    usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+                    contents,
+                });
+            let bind_group = renderer
+                .device
+                .create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.material_layout,
+                    entries: &[wgpu::BindGroupEntry {
+                        binding: 0,
+                        resource: buffer.as_entire_binding(),
+                    }],
+                    label: Some(&format!("{label} Bind Group")),
+                });
+            (buffer, bind_group)
+        });
+        self.materials
+            .insert(id, MaterialPipeline { pipeline, uniform });
+    }
+
+    pub fn unload_material(&mut self, id: AssetId) {
+        self.materials.remove(&id);
+    }
+
+    /// Materials apply to single layer sheets only, and fall back to the default material
+    /// until they're loaded
+    fn pipeline_for(
+        &self,
+        material: AssetId,
+        layered: bool,
+    ) -> (&wgpu::RenderPipeline, Option<&wgpu::BindGroup>) {
+        if layered {
+            if material != DEFAULT_MATERIAL {
+                static WARN: std::sync::Once = std::sync::Once::new();
+                WARN.call_once(|| {
+                    tracing::warn!(
+                        material,
+                        "SpriteMaterials are not supported for layered sheets, using the built-in shader"
+                    );
+                });
+            }
+            return (&self.array_render_pipeline, None);
+        }
+        match self.materials.get(&material) {
+            Some(m) => (&m.pipeline, m.uniform.as_ref().map(|(_, b)| b)),
+            None => (&self.render_pipeline, None),
+        }
+    }
+
+    /// Fails if the sheet's texture can not be created, e.g. it exceeds the device's limits or its
+    /// layers differ in size
+    pub fn add_sheet(
+        &mut self,
+        id: AssetId,
+        sheet: &SpriteSheet,
+        renderer: &GraphicsState,
+    ) -> anyhow::Result<()> {
+        let limits = renderer.limits();
+        let (width, height) = sheet.image.dimensions();
+        anyhow::ensure!(
+            width.max(height) <= limits.max_texture_dimension_2d,
+            "SpriteSheet of {width}x{height} exceeds the device's max texture size of {}",
+            limits.max_texture_dimension_2d
+        );
+        anyhow::ensure!(
+            sheet.layers.len() < limits.max_texture_array_layers as usize,
+            "SpriteSheet has {} layers, the device supports at most {}",
+            sheet.layers.len() + 1,
+            limits.max_texture_array_layers
+        );
+        let layered = sheet.is_layered();
+        let (texture, dimension) = if layered {
+            let images = std::iter::once(&sheet.image)
+                .chain(sheet.layers.iter())
+                .cloned()
+                .collect::<Vec<_>>();
+            let texture = Texture::from_images(
+                renderer.device(),
+                renderer.queue(),
+                &images,
+                None,
+                sheet.address_mode,
+                sheet.generate_mips,
+            )
+            .context("Failed to create texture array")?;
+            (texture, wgpu::TextureViewDimension::D2Array)
+        } else {
+            let texture = Texture::from_image(
+                renderer.device(),
+                renderer.queue(),
+                &sheet.image,
+                None,
+                sheet.address_mode,
+                sheet.generate_mips,
+            )
+            .context("Failed to create texture")?;
+            (texture, wgpu::TextureViewDimension::D2)
+        };
 
-        let (_, spritesheet_bind_group) = texture_to_bindings(&renderer.device, &texture);
+        let (_, spritesheet_bind_group) =
+            texture_to_bindings(&renderer.device, &texture, dimension);
         let sheet_gpu = sheet.extract();
 
         let spritesheet_buffer =
@@ -311,18 +1014,14 @@ impl SpritePipeline {
         self.sheets.insert(
             id,
             SpriteRenderingData {
-                count: 0,
-                instance_gpu: renderer.device.create_buffer(&wgpu::BufferDescriptor {
-                    label: Some("Sprite Instance Buffer"),
-                    mapped_at_creation: false,
-                    usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-                    size: 0,
-                }),
+                instances: Default::default(),
                 spritesheet_gpu,
                 spritesheet_bind_group,
                 texture,
+                layered,
             },
         );
+        Ok(())
     }
 
     pub fn new(renderer: &GraphicsState) -> Self {
@@ -344,71 +1043,69 @@ impl SpritePipeline {
                 });
         let shader = renderer
             .device
-            .create_shader_module(include_wgsl!("sprite-shader.wgsl"));
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("sprite-shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    concat!(
+                        include_str!("sprite-vertex.wgsl"),
+                        include_str!("sprite-fragment.wgsl")
+                    )
+                    .into(),
+                ),
+            });
+        let array_shader = renderer
+            .device
+            .create_shader_module(wgpu::ShaderModuleDescriptor {
+                label: Some("sprite-array-shader"),
+                source: wgpu::ShaderSource::Wgsl(
+                    concat!(
+                        include_str!("sprite-vertex.wgsl"),
+                        include_str!("sprite-array-fragment.wgsl")
+                    )
+                    .into(),
+                ),
+            });
 
-        let texture_bind_group_layout =
-            texture_bind_group_layout(&renderer.device, "sprite-texture-layout");
+        let texture_bind_group_layout = texture_bind_group_layout(
+            &renderer.device,
+            "sprite-texture-layout",
+            wgpu::TextureViewDimension::D2,
+        );
+        let array_texture_bind_group_layout = texture_bind_group_layout(
+            &renderer.device,
+            "sprite-array-texture-layout",
+            wgpu::TextureViewDimension::D2Array,
+        );
 
-        let render_pipeline_layout =
-            renderer
-                .device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Sprite Render Pipeline Layout"),
-                    bind_group_layouts: &[
-                        &renderer.camera_bind_group_layout,
-                        &texture_bind_group_layout,
-                        &sprite_sheet_layout,
-                    ],
-                    push_constant_ranges: &[],
-                });
-        let render_pipeline =
+        let render_pipeline = create_sprite_render_pipeline(
+            renderer,
+            &shader,
+            &[&texture_bind_group_layout, &sprite_sheet_layout],
+            BlendMode::Straight,
+            "Sprite Render Pipeline",
+        );
+        let array_render_pipeline = create_sprite_render_pipeline(
+            renderer,
+            &array_shader,
+            &[&array_texture_bind_group_layout, &sprite_sheet_layout],
+            BlendMode::Straight,
+            "Sprite Array Render Pipeline",
+        );
+        let material_layout =
             renderer
                 .device
-                .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                    label: Some("Sprite Render Pipeline"),
-                    layout: Some(&render_pipeline_layout),
-                    vertex: wgpu::VertexState {
-                        module: &shader,
-                        entry_point: "vs_main",
-                        buffers: &[Vertex::desc(), SpriteInstanceRaw::desc()],
-                        compilation_options: Default::default(),
-                    },
-                    fragment: Some(wgpu::FragmentState {
-                        module: &shader,
-                        entry_point: "fs_main",
-                        compilation_options: Default::default(),
-                        targets: &[Some(wgpu::ColorTargetState {
-                            format: renderer.config.format,
-                            blend: Some(wgpu::BlendState::ALPHA_BLENDING),
-                            write_mask: wgpu::ColorWrites::ALL,
-                        })],
-                    }),
-                    primitive: wgpu::PrimitiveState {
-                        topology: wgpu::PrimitiveTopology::TriangleList,
-                        strip_index_format: None,
-                        front_face: wgpu::FrontFace::Ccw,
-                        cull_mode: None,
-                        // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
-                        polygon_mode: wgpu::PolygonMode::Fill,
-                        // Requires Features::DEPTH_CLIP_CONTROL
-                        unclipped_depth: false,
-                        // Requires Features::CONSERVATIVE_RASTERIZATION
-                        conservative: false,
-                    },
-                    depth_stencil: Some(wgpu::DepthStencilState {
-                        format: super::texture::Texture::DEPTH_FORMAT,
-                        depth_write_enabled: true,
-                        depth_compare: wgpu::CompareFunction::Less,
-                        stencil: wgpu::StencilState::default(),
-                        bias: wgpu::DepthBiasState::default(),
-                    }),
-                    multisample: wgpu::MultisampleState {
-                        count: 1,
-                        mask: !0,
-                        alpha_to_coverage_enabled: true,
-                    },
-                    multiview: None,
-                    cache: None,
+                .create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some("Sprite Material Uniform Layout"),
+                    entries: &[wgpu::BindGroupLayoutEntry {
+                        binding: 0,
+                        visibility: wgpu::ShaderStages::FRAGMENT,
+                        count: None,
+                        ty: wgpu::BindingType::Buffer {
+                            ty: wgpu::BufferBindingType::Uniform,
+                            has_dynamic_offset: false,
+                            min_binding_size: None,
+                        },
+                    }],
                 });
 
         let vertex_buffer = renderer
@@ -430,8 +1127,13 @@ impl SpritePipeline {
 
         SpritePipeline {
             sheets: Default::default(),
+            failed_sheets: Default::default(),
+            materials: Default::default(),
             sprite_sheet_layout,
+            texture_bind_group_layout,
+            material_layout,
             render_pipeline,
+            array_render_pipeline,
             vertex_buffer,
             index_buffer,
             num_indices,
@@ -443,18 +1145,43 @@ impl SpritePipeline {
         RenderCommandInput {
             render_pass,
             camera,
+            layers,
+            stats,
         }: &mut RenderCommandInput,
     ) {
-        render_pass.set_pipeline(&self.render_pipeline);
         for (_, sheet) in self.sheets.iter() {
-            render_pass.set_bind_group(0, camera, &[]);
-            render_pass.set_bind_group(1, &sheet.spritesheet_bind_group, &[]);
-            render_pass.set_bind_group(2, &sheet.spritesheet_gpu, &[]);
-            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
-            render_pass.set_vertex_buffer(1, sheet.instance_gpu.slice(..));
-            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-
-            render_pass.draw_indexed(0..self.num_indices, 0, 0..sheet.count as u32);
+            // instances are ordered by material, so every pipeline is bound at most once per
+            // sheet. Sheets without visible instances are not bound at all.
+            let mut bound_material = None;
+            for ((material, instance_layers), instances) in sheet.instances.iter() {
+                if instances.count == 0 || !layers.intersects(RenderLayers(*instance_layers)) {
+                    continue;
+                }
+                if bound_material != Some(*material) {
+                    let (pipeline, uniform) = self.pipeline_for(*material, sheet.layered);
+                    render_pass.set_pipeline(pipeline);
+                    stats.pipeline_switches += 1;
+                    if bound_material.is_none() {
+                        render_pass.set_bind_group(0, camera, &[]);
+                        render_pass.set_bind_group(1, &sheet.spritesheet_bind_group, &[]);
+                        render_pass.set_bind_group(2, &sheet.spritesheet_gpu, &[]);
+                        stats.bind_group_switches += 3;
+                        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+                        render_pass.set_index_buffer(
+                            self.index_buffer.slice(..),
+                            wgpu::IndexFormat::Uint16,
+                        );
+                    }
+                    if let Some(uniform) = uniform {
+                        render_pass.set_bind_group(3, uniform, &[]);
+                        stats.bind_group_switches += 1;
+                    }
+                    bound_material = Some(*material);
+                }
+                render_pass.set_vertex_buffer(1, instances.instance_gpu.slice(..));
+                render_pass.draw_indexed(0..self.num_indices, 0, 0..instances.count as u32);
+                stats.draw(instances.count as u32);
+            }
         }
     }
 }
@@ -476,6 +1203,9 @@ struct SpriteInstanceRaw {
     index: u32,
     /// bool
     flip: u32,
+    anchor: [f32; 2],
+    /// `[min_x, min_y, max_x, max_y]`, all zeroes to use the sheet's grid
+    uv_rect: [f32; 4],
 }
 
 impl SpriteInstanceRaw {
@@ -501,6 +1231,16 @@ impl SpriteInstanceRaw {
                     shader_location: 4,
                     format: wgpu::VertexFormat::Uint32,
                 },
+                wgpu::VertexAttribute {
+                    offset: ROW_SIZE + 8,
+                    shader_location: 5,
+                    format: wgpu::VertexFormat::Float32x2,
+                },
+                wgpu::VertexAttribute {
+                    offset: ROW_SIZE * 2,
+                    shader_location: 6,
+                    format: wgpu::VertexFormat::Float32x4,
+                },
             ],
         }
     }
@@ -541,10 +1281,14 @@ pub struct SpriteRendererPlugin;
 impl Plugin for SpriteRendererPlugin {
     fn build(self, app: &mut crate::App) {
         app.add_plugin(AssetsPlugin::<SpriteSheet>::default());
+        app.add_plugin(AssetsPlugin::<SpriteMaterial>::default());
+        app.add_plugin(ExtractionPlugin::<Handle<SpriteMaterial>>::default());
         app.add_plugin(ExtractionPlugin::<SpriteInstanceRaw>::default());
+        app.add_plugin(EventsPlugin::<SpriteAnimationEvent>::default());
         app.with_stage(Stage::Update, |s| {
             // putting this system in update means that the last frame's data will be presented
-            s.add_system(compute_sprite_instances)
+            s.add_system(update_sprite_animations)
+                .add_system(compute_sprite_instances.after(update_sprite_animations))
                 .add_system(insert_missing_cull)
                 .add_system(update_visible)
                 .add_system(update_invisible);
@@ -554,6 +1298,7 @@ impl Plugin for SpriteRendererPlugin {
             RenderPass::Transparent,
         ));
         app.extact_stage.add_system(add_missing_sheets);
+        app.extact_stage.add_system(add_missing_materials);
 
         if let Some(ref mut app) = app.render_app {
             app.add_startup_system(setup);
@@ -564,13 +1309,88 @@ impl Plugin for SpriteRendererPlugin {
             });
             app.with_stage(Stage::Update, |s| {
                 s.add_system(unload_sheets)
-                    .add_system(update_sprite_pipelines);
+                    .add_system(insert_missing_sprite_motion)
+                    .add_system(interpolate_sprites.after(insert_missing_sprite_motion))
+                    .add_system(update_sprite_pipelines.after(interpolate_sprites));
             });
         }
     }
 }
 
-fn texture_bind_group_layout(device: &wgpu::Device, label: &str) -> wgpu::BindGroupLayout {
+/// `bind_group_layouts` are the layouts following the camera's
+fn create_sprite_render_pipeline(
+    renderer: &GraphicsState,
+    shader: &wgpu::ShaderModule,
+    bind_group_layouts: &[&wgpu::BindGroupLayout],
+    blend: BlendMode,
+    label: &str,
+) -> wgpu::RenderPipeline {
+    let bind_group_layouts = std::iter::once(&renderer.camera_bind_group_layout)
+        .chain(bind_group_layouts.iter().copied())
+        .collect::<Vec<_>>();
+    let render_pipeline_layout =
+        renderer
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some(&format!("{label} Layout")),
+                bind_group_layouts: &bind_group_layouts,
+                push_constant_ranges: &[],
+            });
+    renderer
+        .device
+        .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some(label),
+            layout: Some(&render_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc(), SpriteInstanceRaw::desc()],
+                compilation_options: Default::default(),
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: shader,
+                entry_point: "fs_main",
+                compilation_options: Default::default(),
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: renderer.config.format,
+                    blend: Some(blend.blend_state()),
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState {
+                topology: wgpu::PrimitiveTopology::TriangleList,
+                strip_index_format: None,
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: None,
+                // Setting this to anything other than Fill requires Features::NON_FILL_POLYGON_MODE
+                polygon_mode: wgpu::PolygonMode::Fill,
+                // Requires Features::DEPTH_CLIP_CONTROL
+                unclipped_depth: false,
+                // Requires Features::CONSERVATIVE_RASTERIZATION
+                conservative: false,
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: super::texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                depth_compare: wgpu::CompareFunction::Less,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState {
+                count: 1,
+                mask: !0,
+                alpha_to_coverage_enabled: true,
+            },
+            multiview: None,
+            cache: None,
+        })
+}
+
+fn texture_bind_group_layout(
+    device: &wgpu::Device,
+    label: &str,
+    view_dimension: wgpu::TextureViewDimension,
+) -> wgpu::BindGroupLayout {
     device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
         entries: &[
             wgpu::BindGroupLayoutEntry {
@@ -578,7 +1398,7 @@ fn texture_bind_group_layout(device: &wgpu::Device, label: &str) -> wgpu::BindGr
                 visibility: wgpu::ShaderStages::FRAGMENT,
                 ty: wgpu::BindingType::Texture {
                     multisampled: false,
-                    view_dimension: wgpu::TextureViewDimension::D2,
+                    view_dimension,
                     sample_type: wgpu::TextureSampleType::Float { filterable: true },
                 },
                 count: None,
@@ -599,8 +1419,10 @@ fn texture_bind_group_layout(device: &wgpu::Device, label: &str) -> wgpu::BindGr
 fn texture_to_bindings(
     device: &wgpu::Device,
     texture: &texture::Texture,
+    view_dimension: wgpu::TextureViewDimension,
 ) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
-    let texture_bind_group_layout = texture_bind_group_layout(device, "texture_bind_group_layout");
+    let texture_bind_group_layout =
+        texture_bind_group_layout(device, "texture_bind_group_layout", view_dimension);
     let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
         layout: &texture_bind_group_layout,
         entries: &[
@@ -617,3 +1439,311 @@ fn texture_to_bindings(
     });
     (texture_bind_group_layout, diffuse_bind_group)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_interpolate_sprites() {
+        let dt = std::time::Duration::from_secs(10);
+        let tick = instant::Instant::now() - dt / 2;
+
+        let mut world = World::new(1);
+        world.insert_resource(RenderInterpolation { enabled: true });
+        world.insert_resource(crate::Time(tick));
+        world.insert_resource(crate::DeltaTime(dt));
+        world
+            .run_system(move |mut cmd: Commands| {
+                cmd.spawn()
+                    .insert(SpriteInstanceRaw {
+                        pos_scale: [10.0, 0.0, 0.0, 1.0],
+                        ..Default::default()
+                    })
+                    .insert(SpriteMotion {
+                        prev: [0.0, 0.0, 0.0, 1.0],
+                        curr: [0.0, 0.0, 0.0, 1.0],
+                        tick: tick - dt,
+                    });
+            })
+            .unwrap();
+
+        let x = |world: &mut World| {
+            world.run_view_system(|q: Query<&SpriteInstanceRaw>| {
+                q.iter().next().unwrap().pos_scale[0]
+            })
+        };
+
+        world.run_system(interpolate_sprites).unwrap();
+        let halfway = x(&mut world);
+        assert!((halfway - 5.0).abs() < 0.1, "{halfway}");
+
+        // the render world was not updated, the next frame keeps interpolating from the same
+        // positions
+        world.run_system(interpolate_sprites).unwrap();
+        assert!((x(&mut world) - halfway).abs() < 0.1);
+
+        world.insert_resource(RenderInterpolation { enabled: false });
+        world.run_system(interpolate_sprites).unwrap();
+        assert_eq!(x(&mut world), 10.0);
+    }
+
+    #[test]
+    fn test_from_grid() {
+        let sheet = SpriteSheet::from_grid(DynamicImage::new_rgba8(256, 128), 4, 2, Vec2::ZERO);
+
+        assert_eq!(sheet.box_size, Vec2::splat(64.0));
+        assert_eq!(sheet.num_cols, 4);
+        assert_eq!(sheet.frame_count(), 8);
+
+        assert_eq!(sheet.uv_rect(0), (Vec2::ZERO, Vec2::new(0.25, 0.5)));
+        assert_eq!(
+            sheet.uv_rect(5),
+            (Vec2::new(0.25, 0.5), Vec2::new(0.5, 1.0))
+        );
+    }
+
+    #[test]
+    fn test_materials_are_binned_separately() {
+        let mut sheets = Assets::<SpriteSheet>::default();
+        let mut materials = Assets::<SpriteMaterial>::default();
+        let sheet = sheets
+            .insert(SpriteSheet::from_grid(
+                DynamicImage::new_rgba8(2, 2),
+                1,
+                1,
+                Vec2::ZERO,
+            ))
+            .downgrade();
+        let a = materials.insert(SpriteMaterial::default());
+        let b = materials.insert(SpriteMaterial::default());
+        let (a, b) = (a.downgrade(), b.downgrade());
+
+        let default = instance_bin_key(&sheet, None, None);
+        let key_a = instance_bin_key(&sheet, None, Some(&a));
+        let key_b = instance_bin_key(&sheet, None, Some(&b));
+        assert_eq!(default.1, DEFAULT_MATERIAL);
+        assert_ne!(key_a, default);
+        assert_ne!(key_a, key_b);
+        assert_eq!(key_a, instance_bin_key(&sheet, None, Some(&a)));
+    }
+
+    #[test]
+    fn test_from_layers() {
+        let layer = || DynamicImage::new_rgba8(64, 64);
+
+        let sheet =
+            SpriteSheet::from_layers(Vec2::ZERO, Vec2::splat(32.0), 2, vec![layer(), layer()])
+                .unwrap();
+        assert!(sheet.is_layered());
+        assert_eq!(sheet.layers.len(), 1);
+
+        assert!(SpriteSheet::from_layers(Vec2::ZERO, Vec2::splat(32.0), 2, vec![]).is_err());
+        assert!(SpriteSheet::from_layers(
+            Vec2::ZERO,
+            Vec2::splat(32.0),
+            2,
+            vec![layer(), DynamicImage::new_rgba8(64, 32)]
+        )
+        .is_err());
+        assert!(SpriteSheet::from_layers(
+            Vec2::ZERO,
+            Vec2::splat(32.0),
+            2,
+            vec![DynamicImage::new_rgba8(1, 1); MAX_SHEET_LAYERS as usize + 1]
+        )
+        .is_err());
+    }
+
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn test_parallel_gather_keeps_order() {
+        let mut assets = Assets::default();
+        let sheets = [0, 1].map(|_| {
+            assets.insert(SpriteSheet::from_grid(
+                DynamicImage::new_rgba8(4, 4),
+                1,
+                1,
+                Vec2::ZERO,
+            ))
+        });
+        let weak = sheets.each_ref().map(|h| h.downgrade());
+
+        let mut world = World::new(4);
+        world
+            .run_system(move |mut cmd: Commands| {
+                for i in 0..3 * GATHER_CHUNK_SIZE as u32 {
+                    cmd.spawn()
+                        .insert(weak[i as usize % 2].clone())
+                        .insert(SpriteInstanceRaw {
+                            index: i,
+                            ..Default::default()
+                        });
+                }
+            })
+            .unwrap();
+
+        let (parallel, serial) = world.run_view_system(
+            |q: Query<(
+                &WeakHandle<SpriteSheet>,
+                &SpriteInstanceRaw,
+                Option<&RenderLayers>,
+                Option<&WeakHandle<SpriteMaterial>>,
+            )>,
+             pool: Res<JobPool>| {
+                let mut parallel = InstanceBins::default();
+                gather_instances(&q, &mut parallel, false, &pool);
+                let mut serial = InstanceBins::default();
+                gather_instances_serial(&q, &mut serial);
+                (parallel, serial)
+            },
+        );
+        assert_eq!(parallel.len(), 2);
+        for (key, instances) in serial {
+            let indices = |v: &[SpriteInstanceRaw]| v.iter().map(|i| i.index).collect::<Vec<_>>();
+            assert_eq!(indices(&parallel[&key]), indices(&instances));
+        }
+    }
+
+    #[test]
+    fn test_despawned_sheet_bins_are_pruned() {
+        let mut assets = Assets::default();
+        let handle = assets.insert(SpriteSheet::from_grid(
+            DynamicImage::new_rgba8(4, 4),
+            1,
+            1,
+            Vec2::ZERO,
+        ));
+        let weak = handle.downgrade();
+
+        let mut world = World::new(4);
+        world
+            .run_system(move |mut cmd: Commands| {
+                for _ in 0..3 {
+                    cmd.spawn()
+                        .insert(weak.clone())
+                        .insert(SpriteInstanceRaw::default());
+                }
+            })
+            .unwrap();
+
+        fn gather(
+            q: Query<(
+                &WeakHandle<SpriteSheet>,
+                &SpriteInstanceRaw,
+                Option<&RenderLayers>,
+                Option<&WeakHandle<SpriteMaterial>>,
+            )>,
+            mut instances: ResMut<SpritePipelineInstances>,
+        ) {
+            for i in instances.0.values_mut() {
+                i.clear();
+            }
+            gather_instances_serial(&q, &mut instances.0);
+            prune_instance_bins(&mut instances.0);
+        }
+        world.insert_resource(SpritePipelineInstances::default());
+
+        world.run_system(gather).unwrap();
+        let key = (handle.id(), DEFAULT_MATERIAL, RenderLayers::default().0);
+        world.run_view_system(move |instances: Res<SpritePipelineInstances>| {
+            assert_eq!(instances.0[&key].len(), 3);
+        });
+
+        world
+            .run_system(
+                |mut cmd: Commands, q: Query<EntityId, With<SpriteInstanceRaw>>| {
+                    for id in q.iter() {
+                        cmd.delete(id);
+                    }
+                },
+            )
+            .unwrap();
+        world.run_system(gather).unwrap();
+        world.run_view_system(|instances: Res<SpritePipelineInstances>| {
+            assert!(instances.0.is_empty());
+        });
+    }
+
+    #[test]
+    fn test_material_uniform() {
+        let material = SpriteMaterial::default().with_uniform(&[1.0f32, 0.5, 0.25, 1.0]);
+
+        assert!(material.fragment_shader.contains("fn fs_main"));
+        assert_eq!(material.uniform.as_ref().unwrap().len(), 16);
+    }
+
+    #[test]
+    fn test_instance_buffer_capacity() {
+        let mut low_usage = 0;
+        assert_eq!(instance_buffer_capacity(0, 100, &mut low_usage), Some(200));
+        assert_eq!(instance_buffer_capacity(200, 100, &mut low_usage), None);
+
+        // shrink only after sustained low usage
+        for _ in 1..INSTANCE_BUFFER_SHRINK_FRAMES {
+            assert_eq!(instance_buffer_capacity(1000, 10, &mut low_usage), None);
+        }
+        assert_eq!(instance_buffer_capacity(1000, 10, &mut low_usage), Some(20));
+        assert_eq!(low_usage, 0);
+    }
+
+    #[test]
+    fn test_pick_sprite() {
+        let proj = glam::Mat4::perspective_lh(std::f32::consts::FRAC_PI_2, 1.0, 1.0, 100.0);
+        let camera = CameraUniform {
+            view_proj: proj,
+            proj,
+            ..Default::default()
+        };
+        let ray = camera.screen_to_ray(Vec2::splat(50.0), Vec2::splat(100.0));
+
+        let mut world = World::new(4);
+        world
+            .run_system(|mut cmd: Commands| {
+                for (i, (x, z, scale, anchor)) in [
+                    (0.0, 20.0, 1.0, Vec2::ZERO),
+                    (0.0, 10.0, 1.0, Vec2::ZERO),
+                    (5.0, 5.0, 1.0, Vec2::ZERO),
+                    (0.0, -5.0, 1.0, Vec2::ZERO),
+                    // anchored at its right edge, the quad spans x in [-0.5, 1.5]
+                    (1.5, 2.0, 2.0, Vec2::new(0.5, 0.0)),
+                ]
+                .into_iter()
+                .enumerate()
+                {
+                    let mut tr =
+                        crate::transform::Transform::from_position(glam::Vec3::new(x, 0.0, z));
+                    tr.scale = glam::Vec3::splat(scale);
+                    cmd.spawn().insert_bundle((
+                        i as u32,
+                        GlobalTransform(tr),
+                        SpriteInstance::default().with_anchor(anchor),
+                    ));
+                }
+            })
+            .unwrap();
+
+        world.run_view_system(
+            |q: Query<(EntityId, &GlobalTransform, &SpriteInstance, &u32)>| {
+                let pick = |indices: &[u32]| {
+                    let hit = pick_sprite(
+                        &ray,
+                        &camera,
+                        q.iter()
+                            .filter(|(_, _, _, i)| indices.contains(i))
+                            .map(|(id, tr, sprite, _)| (id, tr, sprite)),
+                    );
+                    q.iter()
+                        .find(|(id, _, _, _)| Some(*id) == hit)
+                        .map(|(_, _, _, i)| *i)
+                };
+                assert_eq!(pick(&[0, 1, 2, 3]), Some(1));
+                // off-center sprite only
+                assert_eq!(pick(&[2]), None);
+                // the anchor moves the quad under the ray, even though its position is off
+                assert_eq!(pick(&[2, 4]), Some(4));
+                assert_eq!(pick(&[0, 1, 2, 3, 4]), Some(4));
+            },
+        );
+    }
+}
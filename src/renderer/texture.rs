@@ -1,4 +1,6 @@
-use image::GenericImageView;
+use std::borrow::Cow;
+
+use image::{GenericImageView, RgbaImage};
 
 pub struct Texture {
     pub texture: wgpu::Texture,
@@ -7,22 +9,111 @@ pub struct Texture {
     pub size: (u32, u32),
 }
 
+/// `address_mode` controls sampling outside of the `[0, 1]` UV range, e.g. `Repeat` tiles the
+/// texture. `ClampToEdge` is the usual choice for sprites.
+fn create_sampler(
+    device: &wgpu::Device,
+    address_mode: wgpu::AddressMode,
+    mipmapped: bool,
+) -> wgpu::Sampler {
+    let min_filter = if mipmapped {
+        wgpu::FilterMode::Linear
+    } else {
+        wgpu::FilterMode::Nearest
+    };
+    device.create_sampler(&wgpu::SamplerDescriptor {
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        address_mode_w: address_mode,
+        mag_filter: wgpu::FilterMode::Linear,
+        min_filter,
+        mipmap_filter: min_filter,
+        ..Default::default()
+    })
+}
+
+/// Number of levels of a full mip chain, down to 1x1
+fn mip_level_count(width: u32, height: u32) -> u32 {
+    u32::BITS - width.max(height).max(1).leading_zeros()
+}
+
+/// Write `img` into mip level 0 of the layer, and its downscaled copies into the rest of the
+/// `mip_level_count` levels. Odd sizes round down, so non-power-of-two images are supported.
+fn write_image(
+    queue: &wgpu::Queue,
+    texture: &wgpu::Texture,
+    img: &RgbaImage,
+    layer: u32,
+    mip_level_count: u32,
+) {
+    let mut level_img = Cow::Borrowed(img);
+    for mip_level in 0..mip_level_count {
+        if mip_level > 0 {
+            let width = (level_img.width() / 2).max(1);
+            let height = (level_img.height() / 2).max(1);
+            level_img = Cow::Owned(image::imageops::resize(
+                level_img.as_ref(),
+                width,
+                height,
+                image::imageops::FilterType::Triangle,
+            ));
+        }
+        let (width, height) = level_img.dimensions();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture,
+                mip_level,
+                origin: wgpu::Origin3d {
+                    x: 0,
+                    y: 0,
+                    z: layer,
+                },
+            },
+            level_img.as_raw(),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(4 * width),
+                rows_per_image: Some(height),
+            },
+            wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+    }
+}
+
 impl Texture {
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         bytes: &[u8],
         label: &str,
+        address_mode: wgpu::AddressMode,
+        generate_mips: bool,
     ) -> anyhow::Result<Self> {
         let img = image::load_from_memory(bytes)?;
-        Self::from_image(device, queue, &img, Some(label))
+        Self::from_image(
+            device,
+            queue,
+            &img,
+            Some(label),
+            address_mode,
+            generate_mips,
+        )
     }
 
+    /// `generate_mips` builds the full mip chain on the CPU, reducing shimmering of minified
+    /// textures. Pixel art usually wants it off.
     pub fn from_image(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         img: &image::DynamicImage,
         label: Option<&str>,
+        address_mode: wgpu::AddressMode,
+        generate_mips: bool,
     ) -> anyhow::Result<Self> {
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
@@ -32,10 +123,15 @@ impl Texture {
             height: dimensions.1,
             depth_or_array_layers: 1,
         };
+        let mip_level_count = if generate_mips {
+            mip_level_count(dimensions.0, dimensions.1)
+        } else {
+            1
+        };
         let texture = device.create_texture(&wgpu::TextureDescriptor {
             label,
             size,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
             format: wgpu::TextureFormat::Rgba8UnormSrgb,
@@ -43,32 +139,82 @@ impl Texture {
             view_formats: &[],
         });
 
-        queue.write_texture(
-            wgpu::ImageCopyTexture {
-                aspect: wgpu::TextureAspect::All,
-                texture: &texture,
-                mip_level: 0,
-                origin: wgpu::Origin3d::ZERO,
-            },
-            &rgba,
-            wgpu::ImageDataLayout {
-                offset: 0,
-                bytes_per_row: Some(4 * dimensions.0),
-                rows_per_image: Some(dimensions.1),
-            },
-            size,
-        );
+        write_image(queue, &texture, &rgba, 0, mip_level_count);
 
         let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-            address_mode_u: wgpu::AddressMode::ClampToEdge,
-            address_mode_v: wgpu::AddressMode::ClampToEdge,
-            address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Linear,
-            min_filter: wgpu::FilterMode::Nearest,
-            mipmap_filter: wgpu::FilterMode::Nearest,
+        let sampler = create_sampler(device, address_mode, generate_mips);
+
+        Ok(Self {
+            texture,
+            view,
+            sampler,
+            size: dimensions,
+        })
+    }
+
+    /// Create a texture array from a list of equally sized images, one layer per image
+    ///
+    /// The number of layers is limited by `Limits::max_texture_array_layers`
+    pub fn from_images(
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        images: &[image::DynamicImage],
+        label: Option<&str>,
+        address_mode: wgpu::AddressMode,
+        generate_mips: bool,
+    ) -> anyhow::Result<Self> {
+        let Some(first) = images.first() else {
+            anyhow::bail!("Texture arrays require at least 1 image");
+        };
+        let dimensions = first.dimensions();
+        if images.iter().any(|img| img.dimensions() != dimensions) {
+            anyhow::bail!("All layers of a texture array must have the same size");
+        }
+        let max_layers = device.limits().max_texture_array_layers;
+        if images.len() > max_layers as usize {
+            anyhow::bail!(
+                "Texture array has {} layers, but the device supports at most {}",
+                images.len(),
+                max_layers
+            );
+        }
+
+        let size = wgpu::Extent3d {
+            width: dimensions.0,
+            height: dimensions.1,
+            depth_or_array_layers: images.len() as u32,
+        };
+        let mip_level_count = if generate_mips {
+            mip_level_count(dimensions.0, dimensions.1)
+        } else {
+            1
+        };
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label,
+            size,
+            mip_level_count,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        for (layer, img) in images.iter().enumerate() {
+            write_image(
+                queue,
+                &texture,
+                &img.to_rgba8(),
+                layer as u32,
+                mip_level_count,
+            );
+        }
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor {
+            dimension: Some(wgpu::TextureViewDimension::D2Array),
             ..Default::default()
         });
+        let sampler = create_sampler(device, address_mode, generate_mips);
 
         Ok(Self {
             texture,
@@ -124,3 +270,18 @@ impl Texture {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mip_level_count() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(0, 0), 1);
+        assert_eq!(mip_level_count(32, 32), 6);
+        // non-power-of-two sizes round down, 100 -> 50 -> 25 -> 12 -> 6 -> 3 -> 1
+        assert_eq!(mip_level_count(100, 30), 7);
+        assert_eq!(mip_level_count(1, 256), 9);
+    }
+}
@@ -1,7 +1,9 @@
+pub mod d2;
+
 use cecs::prelude::*;
 use glam::{Quat, Vec3};
 
-use crate::Plugin;
+use crate::{commands_ext::insert_missing, ParallelConfig, Plugin};
 
 pub fn transform_bundle(tr: Transform) -> impl Bundle {
     (tr, GlobalTransform(tr))
@@ -14,7 +16,14 @@ pub fn spawn_child(
 ) {
     let cmd = cmd.spawn();
     fun(cmd);
-    cmd.insert_bundle((Parent(parent), AppendChild));
+    cmd.insert_bundle(child_of(parent));
+}
+
+/// Insert into an entity to make it a child of `parent`.
+///
+/// The entity is added to the parent's [Children] by the transform systems, in `PostUpdate`.
+pub fn child_of(parent: EntityId) -> impl Bundle {
+    (Parent(parent), AppendChild)
 }
 
 fn clean_children(mut q: Query<&mut Children>, exists: Query<&Parent>) {
@@ -60,6 +69,18 @@ struct AppendChild;
 
 pub struct Parent(EntityId);
 
+impl Parent {
+    /// Prefer [child_of] when building hierarchies, inserting a `Parent` on its own does not
+    /// register the entity in the parent's [Children]
+    pub fn new(parent: EntityId) -> Self {
+        Self(parent)
+    }
+
+    pub fn get(&self) -> EntityId {
+        self.0
+    }
+}
+
 impl std::ops::Deref for Parent {
     type Target = EntityId;
 
@@ -68,9 +89,30 @@ impl std::ops::Deref for Parent {
     }
 }
 
+/// Entities whose [Parent] is this entity. Maintained by the transform systems.
+///
+/// Read-only, change the hierarchy via [spawn_child] or [child_of] instead.
 pub struct Children(smallvec::SmallVec<[EntityId; 4]>);
 unsafe impl Send for Children {}
 
+impl Children {
+    pub fn iter(&self) -> std::slice::Iter<'_, EntityId> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn contains(&self, id: EntityId) -> bool {
+        self.0.contains(&id)
+    }
+}
+
 impl std::ops::Deref for Children {
     type Target = [EntityId];
 
@@ -82,6 +124,43 @@ impl std::ops::Deref for Children {
 #[derive(Default, Debug, Clone, Copy)]
 pub struct GlobalTransform(pub Transform);
 
+impl From<&Transform> for GlobalTransform {
+    fn from(tr: &Transform) -> Self {
+        Self(*tr)
+    }
+}
+
+impl GlobalTransform {
+    /// World-space position
+    pub fn translation(&self) -> Vec3 {
+        self.0.pos
+    }
+
+    pub fn rotation(&self) -> Quat {
+        self.0.rot
+    }
+
+    pub fn scale(&self) -> Vec3 {
+        self.0.scale
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.0.forward()
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.0.right()
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.0.up()
+    }
+
+    pub fn compute_matrix(&self) -> glam::Mat4 {
+        self.0.compute_matrix()
+    }
+}
+
 #[derive(Debug, Clone, Copy)]
 pub struct Transform {
     pub pos: Vec3,
@@ -94,6 +173,22 @@ impl Transform {
         glam::Mat4::from_scale_rotation_translation(self.scale, self.rot, self.pos)
     }
 
+    /// Local +Z axis. The engine uses left-handed coordinates, so this is the direction the
+    /// transform is facing.
+    pub fn forward(&self) -> Vec3 {
+        self.rot * Vec3::Z
+    }
+
+    /// Local +X axis
+    pub fn right(&self) -> Vec3 {
+        self.rot * Vec3::X
+    }
+
+    /// Local +Y axis
+    pub fn up(&self) -> Vec3 {
+        self.rot * Vec3::Y
+    }
+
     pub fn inverse(&self) -> Self {
         debug_assert!(self.rot.is_normalized());
         Self {
@@ -157,6 +252,9 @@ impl Plugin for TransformPlugin {
     fn build(self, app: &mut crate::App) {
         app.with_stage(crate::Stage::PostUpdate, |s| {
             s.add_system(insert_missing_children)
+                // entities without a GlobalTransform would never be updated, and silently
+                // skipped by rendering and culling
+                .add_system(insert_missing::<Transform, GlobalTransform>)
                 .add_system(clean_children)
                 .add_system(append_new_children.after(insert_missing_children));
         })
@@ -167,18 +265,34 @@ impl Plugin for TransformPlugin {
     }
 }
 
-fn update_root_transforms(mut root: Query<(&Transform, &mut GlobalTransform), WithOut<Parent>>) {
+/// Copies the [Transform] of entities without a [Parent] into their [GlobalTransform], in the
+/// `Transform` stage
+pub fn update_root_transforms(
+    mut root: Query<(&Transform, &mut GlobalTransform), WithOut<Parent>>,
+) {
     for (tr, global_tr) in root.iter_mut() {
         global_tr.0 = *tr;
     }
 }
 
+/// Propagates transforms down the hierarchy, in the `Transform` stage
 #[cfg(feature = "parallel")]
-fn update_child_transforms(
+pub fn update_child_transforms(
     root: Query<(&Transform, &Children), WithOut<Parent>>,
     qchildren: Query<(&Transform, &mut GlobalTransform, Option<&Children>)>,
     pool: Res<JobPool>,
+    config: Option<Res<ParallelConfig>>,
 ) {
+    if config.map(|c| c.single_threaded).unwrap_or(false) {
+        for (tr, children) in root.iter() {
+            for child_id in children.iter() {
+                unsafe {
+                    update_children_transforms_recursive_serial(&qchildren, tr, *child_id);
+                }
+            }
+        }
+        return;
+    }
     root.par_for_each(|(tr, children)| {
         pool.scope(|s| {
             children.chunks(256).for_each(|chunk| unsafe {
@@ -194,6 +308,12 @@ fn update_child_transforms(
     });
 }
 
+/// # Safety
+///
+/// The hierarchy must be a tree, i.e. every child entity is listed in exactly one `Children`
+/// and there are no cycles. Then every job writes a distinct `GlobalTransform`, which is what
+/// makes the unchecked mutable fetches sound. The parent's `GlobalTransform` is only read after
+/// it has been written by the enclosing job.
 #[cfg(feature = "parallel")]
 unsafe fn update_children_transforms_recursive(
     qchildren: &Query<(&Transform, &mut GlobalTransform, Option<&Children>)>,
@@ -226,20 +346,20 @@ unsafe fn update_children_transforms_recursive(
     }
 }
 
+/// Propagates transforms down the hierarchy, in the `Transform` stage
 #[cfg(not(feature = "parallel"))]
-fn update_child_transforms(
+pub fn update_child_transforms(
     root: Query<(&Transform, &Children), WithOut<Parent>>,
     qchildren: Query<(&Transform, &mut GlobalTransform, Option<&Children>)>,
 ) {
     root.par_for_each(|(tr, children)| {
         children.iter().for_each(|child_id| unsafe {
-            update_children_transforms_recursive(&qchildren, tr, *child_id);
+            update_children_transforms_recursive_serial(&qchildren, tr, *child_id);
         });
     });
 }
 
-#[cfg(not(feature = "parallel"))]
-unsafe fn update_children_transforms_recursive(
+unsafe fn update_children_transforms_recursive_serial(
     qchildren: &Query<(&Transform, &mut GlobalTransform, Option<&Children>)>,
     parent_tr: &Transform,
     child_id: EntityId,
@@ -253,7 +373,7 @@ unsafe fn update_children_transforms_recursive(
     if let Some(children) = children {
         let children = (&*children).0.as_slice();
         children.iter().for_each(|child_id| {
-            update_children_transforms_recursive(qchildren, &global_tr.0, *child_id);
+            update_children_transforms_recursive_serial(qchildren, &global_tr.0, *child_id);
         });
     }
 }
@@ -267,3 +387,90 @@ impl Default for Transform {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_global_transform_test() {
+        let mut world = World::new(4);
+        world
+            .run_system(|mut cmd: Commands| {
+                cmd.spawn().insert(Transform::from_position(Vec3::X));
+            })
+            .unwrap();
+        world
+            .run_system(insert_missing::<Transform, GlobalTransform>)
+            .unwrap();
+
+        world.run_view_system(|q: Query<&GlobalTransform>| {
+            let tr = q.iter().next().unwrap();
+            assert_eq!(tr.translation(), Vec3::X);
+        });
+    }
+
+    #[test]
+    fn children_test() {
+        struct Root;
+
+        let mut world = World::new(4);
+        world
+            .run_system(|mut cmd: Commands| {
+                cmd.spawn()
+                    .insert(Root)
+                    .insert_bundle(transform_bundle(Transform::default()));
+            })
+            .unwrap();
+        let parent =
+            world.run_view_system(|q: Query<EntityId, With<Root>>| q.iter().next().unwrap());
+        world
+            .run_system(move |mut cmd: Commands| {
+                for _ in 0..2 {
+                    cmd.spawn()
+                        .insert_bundle(transform_bundle(Transform::default()))
+                        .insert_bundle(child_of(parent));
+                }
+            })
+            .unwrap();
+        world.run_system(insert_missing_children).unwrap();
+        world.run_system(append_new_children).unwrap();
+
+        world.run_view_system(
+            move |children: Query<&Children>, q: Query<(EntityId, &Parent)>| {
+                // only the root has children
+                let children = children.iter().next().unwrap();
+                assert_eq!(children.len(), 2);
+                for (id, p) in q.iter() {
+                    assert_eq!(p.get(), parent);
+                    assert!(children.contains(id));
+                }
+                assert_eq!(children.iter().count(), 2);
+            },
+        );
+    }
+
+    #[test]
+    fn basis_vectors_test() {
+        let tr = GlobalTransform(Transform {
+            pos: Vec3::new(1.0, 2.0, 3.0),
+            rot: Quat::from_axis_angle(Vec3::Y, std::f32::consts::FRAC_PI_2),
+            ..Default::default()
+        });
+
+        assert_eq!(tr.translation(), Vec3::new(1.0, 2.0, 3.0));
+        assert!(tr.forward().abs_diff_eq(Vec3::X, 1e-6));
+        assert!(tr.right().abs_diff_eq(Vec3::NEG_Z, 1e-6));
+        assert!(tr.up().abs_diff_eq(Vec3::Y, 1e-6));
+    }
+
+    #[test]
+    fn basis_vectors_identity_test() {
+        let tr = GlobalTransform::default();
+
+        assert_eq!(tr.forward(), Vec3::Z);
+        assert_eq!(tr.right(), Vec3::X);
+        assert_eq!(tr.up(), Vec3::Y);
+        assert_eq!(tr.compute_matrix(), glam::Mat4::IDENTITY);
+    }
+}
@@ -0,0 +1,57 @@
+use glam::{Quat, Vec2, Vec3};
+
+use super::Transform;
+
+/// 2D helpers, the transform stays 3D under the hood. The angle is a rotation around the Z axis.
+impl Transform {
+    pub fn from_xy(x: f32, y: f32) -> Self {
+        Self::from_position(Vec3::new(x, y, 0.0))
+    }
+
+    pub fn from_angle(radians: f32) -> Self {
+        Self::from_rotation(Quat::from_rotation_z(radians))
+    }
+
+    pub fn xy(&self) -> Vec2 {
+        self.pos.truncate()
+    }
+
+    /// Keeps the z coordinate
+    pub fn set_xy(&mut self, pos: Vec2) {
+        self.pos = pos.extend(self.pos.z);
+    }
+
+    /// Rotation around the Z axis, in radians
+    pub fn angle(&self) -> f32 {
+        self.rot.to_euler(glam::EulerRot::ZYX).0
+    }
+
+    pub fn set_angle(&mut self, radians: f32) {
+        self.rot = Quat::from_rotation_z(radians);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn angle_round_trip_test() {
+        for angle in [0.0, 0.5, -1.0, 3.0] {
+            let tr = Transform::from_angle(angle);
+            assert!(
+                (tr.angle() - angle).abs() < 1e-5,
+                "{angle} != {}",
+                tr.angle()
+            );
+        }
+    }
+
+    #[test]
+    fn set_xy_keeps_z_test() {
+        let mut tr = Transform::from_position(Vec3::new(1.0, 2.0, 3.0));
+        tr.set_xy(Vec2::new(4.0, 5.0));
+        assert_eq!(tr.pos, Vec3::new(4.0, 5.0, 3.0));
+        assert_eq!(Transform::from_xy(4.0, 5.0).xy(), Vec2::new(4.0, 5.0));
+    }
+}